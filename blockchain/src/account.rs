@@ -20,6 +20,9 @@ pub trait Account {
     fn wallet(&self) -> Option<LocalWallet>;
     fn create_wallet(&mut self);
     fn address(&self) -> Option<Address>;
+
+    /** Remove all private keys held by the account. `create_wallet` needs to be called once the credentials are available again. */
+    fn lock(&mut self);
 }
 
 impl std::fmt::Debug for dyn Account {
@@ -43,7 +46,7 @@ impl Clone for AccountIdentifier {
 
 #[derive(Debug)]
 pub struct SigningAccount<C> where C: Credentials {
-    credentials: Arc<Mutex<C>>,
+    credentials: Option<Arc<Mutex<C>>>,
     wallets: Arc<Mutex<Vec<LocalWallet>>>,
     identifier: AccountIdentifier
 }
@@ -53,7 +56,13 @@ impl<C> SigningAccount<C> where C: Credentials {
 
     pub fn new(identifier: &AccountIdentifier, credentials: Arc<Mutex<C>>) -> Self {
 
-        Self { credentials: credentials, identifier: identifier.clone(), wallets: Arc::new(Mutex::new(Vec::new())) }
+        Self { credentials: Some(credentials), identifier: identifier.clone(), wallets: Arc::new(Mutex::new(Vec::new())) }
+    }
+
+    /** Provide new credentials to a locked account and re-create its wallet. */
+    pub fn unlock(&mut self, credentials: Arc<Mutex<C>>) {
+        self.credentials = Some(credentials);
+        self.create_wallet();
     }
 
     fn create_wallet(identifier: &AccountIdentifier, credentials: Arc<Mutex<C>>) -> LocalWallet {
@@ -91,13 +100,19 @@ impl<C> Account for SigningAccount<C> where C: Credentials {
 
     fn create_wallet(&mut self) {
         //TODO: Support for multiple wallets
+        let credentials = self.credentials.clone().expect("Account is locked. No credentials available");
         let wallet = SigningAccount::<C>::create_wallet(
             &self.identifier,
-            self.credentials.clone());
+            credentials);
         
         self.wallets.lock().unwrap().borrow_mut().push(wallet); //.insert(0, wallet);
     }
 
+    fn lock(&mut self) {
+        self.wallets.lock().unwrap().clear();
+        self.credentials = None;
+    }
+
     fn wallet(&self) -> Option<LocalWallet> {
         match self.wallets.lock() {
             Ok(apa) => {
@@ -0,0 +1,217 @@
+use std::error::Error;
+use std::fmt;
+
+pub use ethers::types::U256;
+
+use crate::chain::TokenData;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    Empty,
+    InvalidCharacter(char),
+    /// More fractional digits than the token has decimals.
+    TooPrecise(u32),
+    Overflow
+}
+
+impl Error for AmountError { }
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AmountError::Empty => write!(f, "No amount given."),
+            AmountError::InvalidCharacter(character) => write!(f, "Invalid character '{character}' in amount."),
+            AmountError::TooPrecise(decimals) => write!(f, "Amount has more than {decimals} decimal places."),
+            AmountError::Overflow => write!(f, "Amount is too large.")
+        }
+    }
+}
+
+/// How the last displayed digit is rounded when an amount is formatted with fewer fraction digits than decimals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Towards zero. Never shows more than is actually there.
+    Down,
+    Up,
+    HalfUp
+}
+
+/// Separators and precision used to parse and format amounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AmountFormat {
+    pub decimal_separator: char,
+    /// Inserted between groups of three integer digits, i.e. ',' in "1,000.5". Ignored when parsing.
+    pub grouping_separator: Option<char>,
+    /// Maximum number of fraction digits shown. `None` shows all significant digits.
+    pub max_fraction_digits: Option<u32>,
+    pub rounding: Rounding
+}
+
+impl Default for AmountFormat {
+    fn default() -> Self {
+        Self { decimal_separator: '.', grouping_separator: None, max_fraction_digits: None, rounding: Rounding::Down }
+    }
+}
+
+const GROUP_SIZE: usize = 3;
+
+impl AmountFormat {
+
+    /// Parse a user entered amount (i.e. "1.000000000000000001") into base units of a currency with `decimals` decimals.
+    pub fn parse(&self, input: &str, decimals: u32) -> Result<U256, AmountError> {
+
+        let input: String = input.trim().chars()
+            .filter(|c| !c.is_whitespace() && Some(*c) != self.grouping_separator)
+            .collect();
+        if input.is_empty() { return Err(AmountError::Empty); }
+
+        let (integer, fraction) = match input.split_once(self.decimal_separator) {
+            Some((integer, fraction)) => (integer, fraction),
+            None => (input.as_str(), "")
+        };
+        if integer.is_empty() && fraction.is_empty() { return Err(AmountError::Empty); }
+        if let Some(invalid) = integer.chars().chain(fraction.chars()).find(|c| !c.is_ascii_digit()) {
+            return Err(AmountError::InvalidCharacter(invalid));
+        }
+
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > decimals as usize { return Err(AmountError::TooPrecise(decimals)); }
+
+        let digits = format!("{}{}{}", integer, fraction, "0".repeat(decimals as usize - fraction.len()));
+        let digits = digits.trim_start_matches('0');
+        if digits.is_empty() { return Ok(U256::zero()); }
+        U256::from_dec_str(digits).map_err(|_| AmountError::Overflow)
+    }
+
+    /// Format `value` base units of a currency with `decimals` decimals.
+    pub fn format(&self, value: U256, decimals: u32) -> String {
+
+        let shown_digits = self.max_fraction_digits.map_or(decimals, |max| max.min(decimals));
+        let value = match self.round(value, decimals - shown_digits) {
+            Some(value) => value,
+            None => return "∞".to_string()
+        };
+
+        let digits = format!("{:0>width$}", value.to_string(), width = decimals as usize + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - decimals as usize);
+        let fraction = fraction[..shown_digits as usize].trim_end_matches('0');
+
+        let mut formatted = self.group(integer);
+        if !fraction.is_empty() {
+            formatted.push(self.decimal_separator);
+            formatted.push_str(fraction);
+        }
+        formatted
+    }
+
+    /// Round `value` to a multiple of 10^`dropped_digits`. `None` on overflow.
+    fn round(&self, value: U256, dropped_digits: u32) -> Option<U256> {
+        if dropped_digits == 0 { return Some(value); }
+        let unit = match U256::from(10).checked_pow(U256::from(dropped_digits)) {
+            Some(unit) => unit,
+            // Larger than any value, everything is dropped.
+            None => return if self.rounding == Rounding::Up && !value.is_zero() { None } else { Some(U256::zero()) }
+        };
+        let remainder = value % unit;
+        let truncated = value - remainder;
+        let round_up = match self.rounding {
+            Rounding::Down => false,
+            Rounding::Up => !remainder.is_zero(),
+            Rounding::HalfUp => remainder >= unit / 2
+        };
+        if round_up { truncated.checked_add(unit) } else { Some(truncated) }
+    }
+
+    fn group(&self, integer: &str) -> String {
+        match self.grouping_separator {
+            None => integer.to_string(),
+            Some(separator) => {
+                let mut grouped = String::new();
+                for (index, digit) in integer.chars().enumerate() {
+                    if index > 0 && (integer.len() - index) % GROUP_SIZE == 0 {
+                        grouped.push(separator);
+                    }
+                    grouped.push(digit);
+                }
+                grouped
+            }
+        }
+    }
+}
+
+/// 10^`decimals`, saturating at `U256::MAX` for absurd decimal counts.
+pub fn denominator(decimals: u32) -> U256 {
+    U256::from(10).checked_pow(U256::from(decimals)).unwrap_or(U256::MAX)
+}
+
+/// Parse a user entered amount of `token` using the default format.
+pub fn parse_amount(input: &str, token: &dyn TokenData) -> Result<U256, AmountError> {
+    AmountFormat::default().parse(input, token.decimals())
+}
+
+/// Format base units of `token` with all significant digits using the default format.
+pub fn format_amount(value: U256, token: &dyn TokenData) -> String {
+    AmountFormat::default().format(value, token.decimals())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn units(value: &str) -> U256 { U256::from_dec_str(value).unwrap() }
+
+    #[test]
+    fn parses_amounts() {
+        let format = AmountFormat::default();
+        assert_eq!(format.parse("1.5", 18), Ok(units("1500000000000000000")));
+        assert_eq!(format.parse(" 0.000000000000000001 ", 18), Ok(U256::one()));
+        assert_eq!(format.parse(".5", 6), Ok(units("500000")));
+        assert_eq!(format.parse("2.", 6), Ok(units("2000000")));
+        assert_eq!(format.parse("007", 0), Ok(units("7")));
+        assert_eq!(format.parse("0.000", 6), Ok(U256::zero()));
+    }
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        let format = AmountFormat::default();
+        assert_eq!(format.parse("", 18), Err(AmountError::Empty));
+        assert_eq!(format.parse(".", 18), Err(AmountError::Empty));
+        assert_eq!(format.parse("1,5", 18), Err(AmountError::InvalidCharacter(',')));
+        assert_eq!(format.parse("-1", 18), Err(AmountError::InvalidCharacter('-')));
+        assert_eq!(format.parse("1.2.3", 18), Err(AmountError::InvalidCharacter('.')));
+        assert_eq!(format.parse("1".repeat(80).as_str(), 18), Err(AmountError::Overflow));
+    }
+
+    #[test]
+    fn rejects_too_many_fraction_digits() {
+        let format = AmountFormat::default();
+        assert_eq!(format.parse("0.1234567", 6), Err(AmountError::TooPrecise(6)));
+        assert_eq!(format.parse("1.5", 0), Err(AmountError::TooPrecise(0)));
+        // Trailing zeros aren't significant.
+        assert_eq!(format.parse("0.1234560000", 6), Ok(units("123456")));
+    }
+
+    #[test]
+    fn round_trips() {
+        let format = AmountFormat::default();
+        for (text, decimals) in [("0", 18), ("1", 18), ("1.5", 18), ("0.000000000000000001", 18), ("123456.789", 6), ("42", 0)] {
+            let value = format.parse(text, decimals).unwrap();
+            assert_eq!(format.format(value, decimals), text);
+        }
+        let grouped = AmountFormat { decimal_separator: ',', grouping_separator: Some('.'), ..AmountFormat::default() };
+        let value = grouped.parse("1.234.567,25", 2).unwrap();
+        assert_eq!(value, units("123456725"));
+        assert_eq!(grouped.format(value, 2), "1.234.567,25");
+    }
+
+    #[test]
+    fn rounds_shown_fraction_digits() {
+        let value = units("1234567");
+        let format = |rounding| AmountFormat { max_fraction_digits: Some(2), rounding: rounding, ..AmountFormat::default() };
+        assert_eq!(format(Rounding::Down).format(value, 6), "1.23");
+        assert_eq!(format(Rounding::Up).format(value, 6), "1.24");
+        assert_eq!(format(Rounding::HalfUp).format(value, 6), "1.23");
+        assert_eq!(format(Rounding::HalfUp).format(units("1235000"), 6), "1.24");
+        assert_eq!(format(Rounding::Up).format(U256::MAX, 6), "∞");
+    }
+}
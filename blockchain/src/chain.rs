@@ -1,16 +1,36 @@
 use std::error::Error;
 use std::sync::{Weak, Mutex, Arc};
 use std::marker::{Send, Sync};
+use ethers::types::U256;
 
 pub type BalanceObserver = dyn BalanceUpdatedObserver + Send + Sync;
 pub type ObserverList = Arc<Mutex<Vec<Weak<Mutex<BalanceObserver>>>>>;
 
+/** Call `balance_updated` on the observers still alive, dropping the others. */
+pub(crate) fn notify(observers: &ObserverList, balance: U256, token: &dyn TokenData) {
+    let mut observers = observers.lock().unwrap();
+    observers.retain(|observer| observer.strong_count() > 0);
+    for observer in observers.iter().filter_map(Weak::upgrade) {
+        observer.lock().unwrap().balance_updated(balance, token);
+    }
+}
+
+/** Remove `observer` from the list, along with observers that are gone. */
+pub(crate) fn remove_observer(observers: &ObserverList, observer: &Weak<Mutex<BalanceObserver>>) {
+    observers.lock().unwrap().retain(|existing| existing.strong_count() > 0 && !Weak::ptr_eq(existing, observer));
+}
+
 use std::fmt::{self};
 
 #[derive(Debug, Clone)]
 pub enum ChainError {
     InvalidAddress(String),
-    NoReceipt        
+    NoReceipt,
+    Locked,
+    InvalidFees,
+    UnknownTransaction(String),
+    TransactionMined(String),
+    UnknownToken(String)
 }
 
 impl Error for ChainError { }
@@ -19,7 +39,12 @@ impl fmt::Display for ChainError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             ChainError::InvalidAddress(address) => write!(f, "Unable to parse the address: '{address}'"),
-            ChainError::NoReceipt => write!(f, "No transaction receipt. Dropped from mempool?") 
+            ChainError::NoReceipt => write!(f, "No transaction receipt. Dropped from mempool?"),
+            ChainError::Locked => write!(f, "Account is locked. Unlock it before signing."),
+            ChainError::InvalidFees => write!(f, "No fees available for the selected speed."),
+            ChainError::UnknownTransaction(hash) => write!(f, "Transaction {hash} is not pending."),
+            ChainError::TransactionMined(hash) => write!(f, "Transaction {hash} is already mined and can't be replaced."),
+            ChainError::UnknownToken(address) => write!(f, "Token {address} is not in the portfolio.")
         }
     }
 }
@@ -28,15 +53,15 @@ pub trait TokenData {
     fn symbol(&self) -> String;
     fn identifier(&self) -> String;
     fn decimals(&self) -> u32;
-    fn denomiator(&self) -> u128;
+    fn denomiator(&self) -> U256;
 }
 
 pub trait BalanceUpdatedObserver {
-	fn balance_updated(&mut self, gwei: u128, token: &dyn TokenData);
+	fn balance_updated(&mut self, balance: U256, token: &dyn TokenData);
 }
 
 pub trait TokenInteractor {
 
 	fn subscribe(&mut self, observer: Weak<Mutex<BalanceObserver>>);
-	fn unsubscribe(&mut self, observer: &Arc<BalanceObserver>);
+	fn unsubscribe(&mut self, observer: &Weak<Mutex<BalanceObserver>>);
 }
\ No newline at end of file
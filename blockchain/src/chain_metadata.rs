@@ -1,7 +1,197 @@
 use std::collections::HashMap;
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use crate::shared::*;
 
+/// Snapshot of https://chainid.network/chains.json limited to well known chains, used when neither
+/// a cached nor a downloaded list is available.
+const BUNDLED_CHAINS: &str = include_str!("../chains.json");
+
+/// Fields of a chain entry that are used. Everything else is dropped to keep the cached list small.
+const KEPT_FIELDS: [&str; 10] = ["name", "chain", "shortName", "chainId", "nativeCurrency", "rpc", "faucets", "explorers", "infoURL", "slip44"];
+
+const FALLBACK_DECIMAL_COUNT: u32 = 18;
+const FALLBACK_SYMBOL_NAME: &str = "<unknown>";
+/// chains.json has no testnet flag. Testnets use coin type 1 of SLIP-44 or are named as such.
+const TESTNET_SLIP44: u64 = 1;
+const TESTNET_NAME_MARKERS: [&str; 6] = ["testnet", "devnet", "sepolia", "goerli", "holesky", "hoodi"];
+/// Endpoints containing a placeholder, i.e. "https://mainnet.infura.io/v3/${INFURA_API_KEY}", need an API key.
+const PLACEHOLDER_START: &str = "${";
+/// Explorers following EIP-3091 serve `/address/<address>` and `/tx/<hash>`.
+const EIP3091: &str = "EIP3091";
+/// Schemes of explorer URLs used for links. Anything else, i.e. "javascript:", is ignored.
+const WEB_SCHEMES: [&str; 2] = ["https://", "http://"];
+
+/// `true` if `url` is a web page URL, i.e. safe to link to.
+pub fn is_web_url(url: &str) -> bool {
+    let url = url.trim_start().to_ascii_lowercase();
+    WEB_SCHEMES.iter().any(|scheme| url.starts_with(scheme))
+}
+
+/// A block explorer web site of a chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Explorer {
+    pub name: String,
+    /// Base URL, i.e. "https://etherscan.io".
+    pub url: String,
+    /// "EIP3091" if the explorer serves the standard paths.
+    #[serde(default)]
+    pub standard: Option<String>
+}
+
+/// Network properties defined by the user, replacing those of the metadata. `None` keeps the metadata's value.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkOverrides {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u32>,
+    /// Base URL of an EIP-3091 explorer, or a template containing `{kind}` ("address" or "tx") and `{value}`,
+    /// i.e. "https://scan.devnet.io/#/{kind}/{value}".
+    pub explorer_url: Option<String>,
+    pub testnet: Option<bool>
+}
+
+impl NetworkOverrides {
+
+    /// `true` if nothing is overridden.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// What is known about a chain, from chains.json or defaults for unknown chains.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainInfo {
+    pub chain_id: u64,
+    pub name: String,
+    pub short_name: Option<String>,
+    pub currency_name: String,
+    pub symbol: String,
+    pub decimals: u32,
+    /// Public RPC endpoints. Some contain API key placeholders, i.e. "${INFURA_API_KEY}".
+    pub rpcs: Vec<String>,
+    pub explorers: Vec<Explorer>,
+    pub info_url: Option<String>,
+    pub testnet: bool,
+    /// Explorer defined by the user, see `NetworkOverrides::explorer_url`. Takes precedence over `explorers`.
+    #[serde(default)]
+    pub explorer_url: Option<String>
+}
+
+impl ChainInfo {
+
+    /// Defaults for a chain missing from the metadata.
+    pub fn unknown(chain_id: u64) -> Self {
+        Self { chain_id: chain_id,
+               name: format!("Chain {}", chain_id),
+               short_name: None,
+               currency_name: FALLBACK_SYMBOL_NAME.to_string(),
+               symbol: FALLBACK_SYMBOL_NAME.to_string(),
+               decimals: FALLBACK_DECIMAL_COUNT,
+               rpcs: vec![],
+               explorers: vec![],
+               info_url: None,
+               testnet: false,
+               explorer_url: None }
+    }
+
+    /// The info with the properties set in `overrides` replaced.
+    pub fn with_overrides(mut self, overrides: &NetworkOverrides) -> Self {
+        if let Some(name) = &overrides.name { self.name = name.clone(); }
+        if let Some(symbol) = &overrides.symbol {
+            // The currency name of unknown chains is only a placeholder.
+            if self.currency_name == FALLBACK_SYMBOL_NAME { self.currency_name = symbol.clone(); }
+            self.symbol = symbol.clone();
+        }
+        if let Some(decimals) = overrides.decimals { self.decimals = decimals; }
+        if let Some(explorer_url) = &overrides.explorer_url { self.explorer_url = Some(explorer_url.clone()); }
+        if let Some(testnet) = overrides.testnet { self.testnet = testnet; }
+        self
+    }
+
+    /// `None` if the entry lacks a chain id. Other missing or malformed fields take the defaults of `unknown`.
+    fn from_raw(chain_info: &HashMap<String, Value>) -> Option<Self> {
+        let chain_id = chain_info.get("chainId")?.as_u64()?;
+        let string = |value: Option<&Value>| value.and_then(Value::as_str).map(str::to_string);
+        let unknown = Self::unknown(chain_id);
+        let currency = chain_info.get("nativeCurrency").and_then(Value::as_object);
+        let name = string(chain_info.get("name")).unwrap_or(unknown.name);
+        let rpcs = chain_info.get("rpc").and_then(Value::as_array).map_or(vec![], |rpcs| {
+            rpcs.iter().filter_map(Value::as_str).map(str::to_string).collect()
+        });
+        let explorers = chain_info.get("explorers").and_then(Value::as_array).map_or(vec![], |explorers| {
+            explorers.iter().filter_map(|explorer| serde_json::from_value::<Explorer>(explorer.clone()).ok()).collect()
+        });
+        let lowercase_name = name.to_lowercase();
+        let testnet = chain_info.get("slip44").and_then(Value::as_u64) == Some(TESTNET_SLIP44)
+            || TESTNET_NAME_MARKERS.iter().any(|marker| lowercase_name.contains(marker));
+
+        Some(Self { chain_id: chain_id,
+                    name: name,
+                    short_name: string(chain_info.get("shortName")),
+                    currency_name: string(currency.and_then(|currency| currency.get("name"))).unwrap_or(unknown.currency_name),
+                    symbol: string(currency.and_then(|currency| currency.get("symbol"))).unwrap_or(unknown.symbol),
+                    decimals: currency.and_then(|currency| currency.get("decimals")).and_then(Value::as_u64)
+                        .and_then(|decimals| u32::try_from(decimals).ok())
+                        .unwrap_or(unknown.decimals),
+                    rpcs: rpcs,
+                    explorers: explorers,
+                    info_url: string(chain_info.get("infoURL")),
+                    testnet: testnet,
+                    explorer_url: None })
+    }
+
+    /// Endpoints usable without an API key, in the listed order.
+    pub fn public_rpcs(&self) -> Vec<String> {
+        self.rpcs.iter().filter(|rpc| !rpc.contains(PLACEHOLDER_START)).cloned().collect()
+    }
+
+    /// How well the chain matches a lowercase search query, lower is better. `None` if it doesn't match.
+    fn search_rank(&self, query: &str) -> Option<u8> {
+        let name = self.name.to_lowercase();
+        let short_name = self.short_name.as_deref().unwrap_or_default().to_lowercase();
+        if self.chain_id.to_string() == query {
+            Some(0)
+        } else if name == query || short_name == query || self.symbol.to_lowercase() == query {
+            Some(1)
+        } else if name.starts_with(query) {
+            Some(2)
+        } else if name.contains(query) {
+            Some(3)
+        } else {
+            None
+        }
+    }
+
+    /// The explorer used for links, preferring one following EIP-3091. Explorers without a web URL are skipped.
+    pub fn explorer(&self) -> Option<&Explorer> {
+        let mut explorers = self.explorers.iter().filter(|explorer| is_web_url(&explorer.url));
+        self.explorers.iter().find(|explorer| explorer.standard.as_deref() == Some(EIP3091) && is_web_url(&explorer.url))
+            .or_else(|| explorers.next())
+    }
+
+    /// Explorer page of an address or token contract.
+    pub fn address_url(&self, address: &str) -> Option<String> {
+        self.explorer_url("address", address)
+    }
+
+    /// Explorer page of a transaction.
+    pub fn transaction_url(&self, hash: &str) -> Option<String> {
+        self.explorer_url("tx", hash)
+    }
+
+    /// `None` unless the resulting URL is a web URL.
+    fn explorer_url(&self, kind: &str, value: &str) -> Option<String> {
+        let url = match &self.explorer_url {
+            Some(template) if template.contains("{value}") => template.replace("{kind}", kind).replace("{value}", value),
+            Some(base_url) => format!("{}/{}/{}", base_url.trim_end_matches('/'), kind, value),
+            None => format!("{}/{}/{}", self.explorer()?.url.trim_end_matches('/'), kind, value)
+        };
+        Some(url).filter(|url| is_web_url(url))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct ChainsMetadata {
 
     url: String,
@@ -14,16 +204,88 @@ impl ChainsMetadata {
         Self {url: url, raw_data: None}
     }
 
+    /// The snapshot bundled with the binary.
+    pub fn bundled(url: String) -> Self {
+        let mut metadata = Self::new(url);
+        if let Err(error) = metadata.parse(BUNDLED_CHAINS) {
+            println!("Unable to parse bundled chains metadata: {}", error);
+        }
+        metadata
+    }
+
+    pub fn url(&self) -> &str { &self.url }
+
     pub async fn download(&mut self) -> Result<()> {
-        let contents = reqwest::get(&self.url).await?.text().await?;
-        let raw_data_array: Vec<HashMap<String,Value>> = serde_json::from_str(&contents).unwrap();
+        let contents = reqwest::get(&self.url).await?.error_for_status()?.text().await?;
+        self.parse(&contents)?;
+        Ok(())
+    }
+
+    /// Replace the metadata by the chains in `contents`, a JSON array in the format of chains.json.
+    /// Malformed entries are skipped. Fails if `contents` isn't an array or has no valid entry.
+    /// Returns the number of chains.
+    pub fn parse(&mut self, contents: &str) -> Result<usize> {
+        let entries: Vec<Value> = serde_json::from_str(contents)?;
         let mut raw_data: HashMap<u64, HashMap<String,Value>> = HashMap::new();
-        for chain_info in raw_data_array.iter() {
-            let chain_id = chain_info["chainId"].as_u64().unwrap();
-            raw_data.insert(chain_id, chain_info.clone());
+        for entry in entries {
+            let chain_info = match entry {
+                Value::Object(chain_info) => chain_info,
+                _ => continue
+            };
+            let chain_id = match chain_info.get("chainId").and_then(Value::as_u64) {
+                Some(chain_id) => chain_id,
+                None => continue
+            };
+            let chain_info: HashMap<String,Value> = chain_info.into_iter()
+                .filter(|(field, _)| KEPT_FIELDS.contains(&field.as_str()))
+                .collect();
+            // The first entry wins, like a lookup in the original list would.
+            raw_data.entry(chain_id).or_insert(chain_info);
+        }
+        if raw_data.is_empty() {
+            return Err("No valid chain in chains metadata.".into());
         }
+        let count = raw_data.len();
         self.raw_data = Some(raw_data);
-        Ok(())
+        Ok(count)
+    }
+
+    /// Compact JSON array of the kept fields, which `parse` accepts.
+    pub fn serialize(&self) -> String {
+        let mut entries: Vec<(&u64, &HashMap<String,Value>)> = self.raw_data.iter().flatten().collect();
+        entries.sort_by_key(|(chain_id, _)| **chain_id);
+        let entries: Vec<Value> = entries.into_iter()
+            .map(|(_, chain_info)| Value::Object(chain_info.clone().into_iter().collect::<Map<String,Value>>()))
+            .collect();
+        Value::Array(entries).to_string()
+    }
+
+    pub fn len(&self) -> usize {
+        self.raw_data.as_ref().map_or(0, |raw_data| raw_data.len())
+    }
+
+    /// Typed metadata of a chain, `None` if the chain is unknown.
+    pub fn get(&self, chain_id: u64) -> Option<ChainInfo> {
+        ChainInfo::from_raw(self.get_chain_info(chain_id)?)
+    }
+
+    /// Typed metadata of a chain, defaults if the chain is unknown.
+    pub fn info(&self, chain_id: u64) -> ChainInfo {
+        self.get(chain_id).unwrap_or_else(|| ChainInfo::unknown(chain_id))
+    }
+
+    /// Chains whose id, name, short name or native symbol match `query`, best matches and mainnets first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<ChainInfo> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return vec![];
+        }
+        let mut matches: Vec<(u8, ChainInfo)> = self.raw_data.iter().flatten()
+            .filter_map(|(_, chain_info)| ChainInfo::from_raw(chain_info))
+            .filter_map(|info| info.search_rank(&query).map(|rank| (rank, info)))
+            .collect();
+        matches.sort_by_key(|(rank, info)| (*rank, info.testnet, info.chain_id));
+        matches.into_iter().take(limit).map(|(_, info)| info).collect()
     }
 
     pub fn get_symbol(&self, chain_id: u64) -> Option<&str> {
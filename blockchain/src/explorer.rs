@@ -0,0 +1,373 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::StringRepresentation;
+use crate::history::*;
+use crate::shared::*;
+use crate::transaction::*;
+
+/// Transactions requested per call. Etherscan refuses pages for which `page * offset` exceeds 10000,
+/// so the import advances `startblock`, and only pages within a block that fills a whole page.
+const PAGE_SIZE: usize = 1000;
+const MAX_PAGE: usize = 10000 / PAGE_SIZE;
+const LAST_BLOCK: u64 = 99999999;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExplorerError {
+    NotConfigured,
+    /// The explorer answered with an error status, i.e. "Invalid API Key".
+    Api(String),
+    InvalidResponse(String),
+    /// A block has more transfers than can be paged through.
+    TooManyTransactions(u64)
+}
+
+impl Error for ExplorerError { }
+
+impl fmt::Display for ExplorerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExplorerError::NotConfigured => write!(f, "No explorer API configured for this chain."),
+            ExplorerError::Api(message) => write!(f, "Explorer API error: {message}"),
+            ExplorerError::InvalidResponse(message) => write!(f, "Invalid explorer API response: {message}"),
+            ExplorerError::TooManyTransactions(block) => write!(f, "Block {block} has too many transactions to import.")
+        }
+    }
+}
+
+/// Endpoint of an Etherscan compatible explorer API, i.e. "https://api.etherscan.io/api".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExplorerApi {
+    pub base_url: String,
+    pub api_key: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerResponse {
+    status: String,
+    message: String,
+    /// The transactions, or an error message.
+    result: Value
+}
+
+/// A transaction as returned by the `txlist` and `tokentx` actions. All numbers are decimal strings.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ExplorerTransaction {
+    pub block_number: String,
+    pub time_stamp: String,
+    pub hash: String,
+    pub from: String,
+    pub to: String,
+    pub value: String,
+    pub gas_price: String,
+    pub gas_used: String,
+    /// "1" for reverted transactions (`txlist` only).
+    pub is_error: String,
+    pub input: String,
+    pub confirmations: String,
+    /// Token contract (`tokentx` only).
+    pub contract_address: String,
+    pub token_symbol: String
+}
+
+impl ExplorerTransaction {
+
+    /// Identifies an entry, a transaction has several `tokentx` entries if it transfers more than once.
+    fn key(&self) -> (String, String, String, String, String) {
+        (self.hash.clone(), self.from.clone(), self.to.clone(), self.value.clone(), self.contract_address.clone())
+    }
+
+    /// A record of the transfer from or to `address`. `None` if the transaction doesn't involve `address` or is malformed.
+    fn record(&self, chain_id: u64, asset: String, symbol: String, address: Address) -> Option<TransactionRecord> {
+        let from = self.from.parse::<Address>().ok()?;
+        // Contract creations have no recipient.
+        let to = self.to.parse::<Address>().unwrap_or_default();
+        let direction = if from == address { Direction::Outgoing } else if to == address { Direction::Incoming } else { return None };
+        let block_number = self.block_number.parse::<u64>().ok()?;
+        let status = if self.is_error == "1" {
+            TransactionStatus::Failed { block_number: block_number }
+        } else {
+            TransactionStatus::Confirmed { block_number: block_number, confirmations: self.confirmations.parse::<u64>().unwrap_or(1) }
+        };
+        let fee = match direction {
+            Direction::Outgoing => U256::from_dec_str(&self.gas_used).ok()
+                .zip(U256::from_dec_str(&self.gas_price).ok())
+                .and_then(|(gas_used, gas_price)| gas_used.checked_mul(gas_price)),
+            Direction::Incoming => None
+        };
+        let created_at = self.time_stamp.parse::<u64>().unwrap_or_default().saturating_mul(1000);
+
+        Some(TransactionRecord { hash: self.hash.parse::<H256>().ok()?,
+                                 chain_id: chain_id.to_string(),
+                                 asset: asset,
+                                 symbol: symbol,
+                                 from: from,
+                                 to: to,
+                                 amount: U256::from_dec_str(&self.value).ok()?,
+                                 fee: fee,
+                                 status: status,
+                                 block_number: Some(block_number),
+                                 direction: direction,
+                                 created_at: created_at,
+                                 updated_at: created_at })
+    }
+}
+
+/// Client of the `account` module of an Etherscan compatible explorer API.
+#[derive(Debug, Clone)]
+pub struct ExplorerClient {
+    api: ExplorerApi,
+    client: reqwest::Client
+}
+
+impl ExplorerClient {
+
+    pub fn new(api: ExplorerApi) -> Self {
+        Self { api: api, client: reqwest::Client::new() }
+    }
+
+    pub fn api(&self) -> &ExplorerApi { &self.api }
+
+    /// Native transactions of `address`, oldest first, starting at block `start_block`.
+    pub async fn transactions(&self, address: Address, start_block: u64) -> Result<Vec<ExplorerTransaction>> {
+        self.request("txlist", address, start_block, 1).await
+    }
+
+    /// ERC-20 transfers from or to `address`, oldest first, starting at block `start_block`.
+    pub async fn token_transfers(&self, address: Address, start_block: u64) -> Result<Vec<ExplorerTransaction>> {
+        self.request("tokentx", address, start_block, 1).await
+    }
+
+    async fn request(&self, action: &str, address: Address, start_block: u64, page: usize) -> Result<Vec<ExplorerTransaction>> {
+        let mut query = vec![
+            ("module", "account".to_string()),
+            ("action", action.to_string()),
+            ("address", address.string_representation()),
+            ("startblock", start_block.to_string()),
+            ("endblock", LAST_BLOCK.to_string()),
+            ("page", page.to_string()),
+            ("offset", PAGE_SIZE.to_string()),
+            ("sort", "asc".to_string())];
+        if let Some(api_key) = &self.api.api_key {
+            query.push(("apikey", api_key.clone()));
+        }
+
+        let contents = self.client.get(&self.api.base_url).query(&query).send().await?.text().await?;
+        let response: ExplorerResponse = serde_json::from_str(&contents)
+            .map_err(|error| ExplorerError::InvalidResponse(error.to_string()))?;
+
+        match response.result {
+            Value::Array(_) => Ok(serde_json::from_value(response.result)
+                .map_err(|error| ExplorerError::InvalidResponse(error.to_string()))?),
+            // Status "0" is also used for an empty result.
+            _ if response.message.starts_with("No transactions found") => Ok(vec![]),
+            Value::String(message) if response.status != "1" => Err(Box::new(ExplorerError::Api(message))),
+            _ => Err(Box::new(ExplorerError::Api(response.message)))
+        }
+    }
+
+    /// Import the native transfers and the transfers of all tokens from or to `address` into `history`.
+    /// Records already in the history are kept, unless the explorer knows their final status. Returns the number of new records.
+    pub async fn import(&self, address: Address, native_symbol: &str, history: &TransactionHistory) -> Result<usize> {
+        let chain_id = history.chain_id();
+        let mut records: Vec<TransactionRecord> = vec![];
+
+        for transaction in self.all(address, false).await? {
+            // Contract calls without value, i.e. token transfers, are imported from `tokentx`.
+            if transaction.value == "0" && transaction.input != "0x" && !transaction.input.is_empty() { continue; }
+            records.extend(transaction.record(chain_id, chain_id.to_string(), native_symbol.to_string(), address));
+        }
+        for transfer in self.all(address, true).await? {
+            let asset = match transfer.contract_address.parse::<Address>() {
+                Ok(contract) => contract.string_representation(),
+                Err(_) => continue
+            };
+            records.extend(transfer.record(chain_id, asset, transfer.token_symbol.clone(), address));
+        }
+
+        Ok(history.import(records))
+    }
+
+    /// All pages of an action. The last block of a page is requested again, as a page may end within a block, 
+    /// and the entries already received are skipped. A block filling a whole page is paged through by `page`.
+    async fn all(&self, address: Address, tokens: bool) -> Result<Vec<ExplorerTransaction>> {
+        let action = if tokens { "tokentx" } else { "txlist" };
+        let mut transactions: Vec<ExplorerTransaction> = vec![];
+        let mut received = HashSet::new();
+        let mut start_block = 0;
+        let mut page = 1;
+        loop {
+            let entries = self.request(action, address, start_block, page).await?;
+            let complete = entries.len() < PAGE_SIZE;
+            let last_block = entries.last().and_then(|transaction| transaction.block_number.parse::<u64>().ok());
+            transactions.extend(entries.into_iter().filter(|transaction| received.insert(transaction.key())));
+            if complete {
+                break;
+            }
+            match last_block {
+                Some(last_block) if last_block > start_block => {
+                    start_block = last_block;
+                    page = 1;
+                },
+                _ if page < MAX_PAGE => page += 1,
+                _ => return Err(Box::new(ExplorerError::TooManyTransactions(start_block)))
+            }
+        }
+        Ok(transactions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use crate::mock;
+
+    /// Serve `respond(query)` for every request, returns the explorer API and the received queries.
+    fn mock_explorer<F>(respond: F) -> (ExplorerApi, Arc<Mutex<Vec<HashMap<String, String>>>>)
+        where F: Fn(&HashMap<String, String>) -> String + Send + 'static {
+        let queries = Arc::new(Mutex::new(vec![]));
+        let received = queries.clone();
+        let url = mock::serve(move |request| {
+            let query: HashMap<String, String> = request.target.split_once('?').map(|(_, query)| query).unwrap_or_default()
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            let body = respond(&query);
+            received.lock().unwrap().push(query);
+            body
+        });
+        (ExplorerApi { base_url: format!("{}/api", url), api_key: Some("KEY".to_string()) }, queries)
+    }
+
+    fn transaction(block_number: u64, index: usize) -> Value {
+        serde_json::json!({
+            "blockNumber": block_number.to_string(),
+            "timeStamp": "1700000000",
+            "hash": format!("0x{:064x}", index),
+            "from": "0x0000000000000000000000000000000000000001",
+            "to": "0x0000000000000000000000000000000000000002",
+            "value": "1000",
+            "gasPrice": "2",
+            "gasUsed": "21000",
+            "isError": "0",
+            "input": "0x",
+            "confirmations": "12"
+        })
+    }
+
+    fn page(result: Vec<Value>) -> String {
+        serde_json::json!({ "status": "1", "message": "OK", "result": result }).to_string()
+    }
+
+    /// Serve `blocks` (the block number of every transaction, ascending) the way Etherscan pages them.
+    fn paged(blocks: Vec<u64>) -> impl Fn(&HashMap<String, String>) -> String {
+        move |query| {
+            let start_block: u64 = query["startblock"].parse().unwrap();
+            let page_number: usize = query["page"].parse().unwrap();
+            let offset: usize = query["offset"].parse().unwrap();
+            let result = blocks.iter().enumerate()
+                .filter(|(_, block)| **block >= start_block)
+                .skip((page_number - 1) * offset)
+                .take(offset)
+                .map(|(index, block)| transaction(*block, index))
+                .collect();
+            page(result)
+        }
+    }
+
+    #[tokio::test]
+    async fn parses_txlist() {
+        let (api, queries) = mock_explorer(|_| page(vec![transaction(7, 1)]));
+        let address = Address::from_low_u64_be(1);
+        let transactions = ExplorerClient::new(api).transactions(address, 5).await.unwrap();
+
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].block_number, "7");
+        assert_eq!(transactions[0].gas_used, "21000");
+        let record = transactions[0].record(1, "1".to_string(), "ETH".to_string(), address).unwrap();
+        assert!(matches!(record.direction, Direction::Outgoing));
+        assert_eq!(record.amount, U256::from(1000));
+        assert_eq!(record.fee, Some(U256::from(42000)));
+        assert_eq!(record.created_at, 1700000000000);
+
+        let query = &queries.lock().unwrap()[0];
+        assert_eq!(query["action"], "txlist");
+        assert_eq!(query["startblock"], "5");
+        assert_eq!(query["apikey"], "KEY");
+    }
+
+    #[tokio::test]
+    async fn parses_tokentx() {
+        let mut transfer = transaction(7, 1);
+        transfer["contractAddress"] = "0x0000000000000000000000000000000000000003".into();
+        transfer["tokenSymbol"] = "USDC".into();
+        let (api, queries) = mock_explorer(move |_| page(vec![transfer.clone()]));
+        let transfers = ExplorerClient::new(api).token_transfers(Address::from_low_u64_be(2), 0).await.unwrap();
+
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].contract_address, "0x0000000000000000000000000000000000000003");
+        assert_eq!(transfers[0].token_symbol, "USDC");
+        let record = transfers[0].record(1, "token".to_string(), "USDC".to_string(), Address::from_low_u64_be(2)).unwrap();
+        assert!(matches!(record.direction, Direction::Incoming));
+        assert_eq!(record.fee, None);
+        assert_eq!(queries.lock().unwrap()[0]["action"], "tokentx");
+    }
+
+    #[tokio::test]
+    async fn no_transactions_found_is_empty() {
+        let (api, _) = mock_explorer(|_| r#"{"status":"0","message":"No transactions found","result":[]}"#.to_string());
+        assert!(ExplorerClient::new(api).transactions(Address::zero(), 0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_api_errors() {
+        let (api, _) = mock_explorer(|_| r#"{"status":"0","message":"NOTOK","result":"Invalid API Key"}"#.to_string());
+        let error = ExplorerClient::new(api).transactions(Address::zero(), 0).await.unwrap_err();
+        assert_eq!(error.to_string(), ExplorerError::Api("Invalid API Key".to_string()).to_string());
+    }
+
+    #[tokio::test]
+    async fn pages_by_block_without_duplicates() {
+        // 3 transactions per block, pages end within blocks.
+        let blocks: Vec<u64> = (0..2500).map(|index| index / 3).collect();
+        let (api, queries) = mock_explorer(paged(blocks));
+        let transactions = ExplorerClient::new(api).all(Address::zero(), false).await.unwrap();
+
+        assert_eq!(transactions.len(), 2500);
+        let hashes: HashSet<&String> = transactions.iter().map(|transaction| &transaction.hash).collect();
+        assert_eq!(hashes.len(), 2500);
+        let start_blocks: Vec<String> = queries.lock().unwrap().iter().map(|query| query["startblock"].clone()).collect();
+        assert_eq!(start_blocks, ["0", "333", "666"]);
+    }
+
+    #[tokio::test]
+    async fn pages_through_a_full_block() {
+        // Block 1 fills more than two pages.
+        let blocks: Vec<u64> = (0..3020).map(|index| match index { 0..=9 => 0, 10..=2509 => 1, _ => 2 }).collect();
+        let (api, queries) = mock_explorer(paged(blocks));
+        let transactions = ExplorerClient::new(api).all(Address::zero(), false).await.unwrap();
+
+        assert_eq!(transactions.len(), 3020);
+        let requests: Vec<(String, String)> = queries.lock().unwrap().iter()
+            .map(|query| (query["startblock"].clone(), query["page"].clone()))
+            .collect();
+        let expected = [("0", "1"), ("1", "1"), ("1", "2"), ("1", "3"), ("2", "1")];
+        assert_eq!(requests, expected.map(|(block, page)| (block.to_string(), page.to_string())));
+    }
+
+    #[tokio::test]
+    async fn rejects_blocks_beyond_the_last_page() {
+        let blocks: Vec<u64> = vec![4; PAGE_SIZE * MAX_PAGE + 1];
+        let (api, _) = mock_explorer(paged(blocks));
+        let error = ExplorerClient::new(api).all(Address::zero(), false).await.unwrap_err();
+        assert_eq!(error.to_string(), ExplorerError::TooManyTransactions(4).to_string());
+    }
+}
@@ -0,0 +1,340 @@
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+
+use crate::rpc::*;
+use crate::shared::*;
+
+/// The fee parameters of a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fees {
+    /// Pre EIP-1559 (type 0) transaction.
+    Legacy { gas_price: U256 },
+    /// EIP-1559 (type 2) transaction.
+    Eip1559 { max_fee_per_gas: U256, max_priority_fee_per_gas: U256 }
+}
+
+impl Fees {
+
+    /// The highest price per unit of gas the transaction may pay.
+    pub fn max_gas_price(&self) -> U256 {
+        match self {
+            Fees::Legacy { gas_price } => *gas_price,
+            Fees::Eip1559 { max_fee_per_gas, .. } => *max_fee_per_gas
+        }
+    }
+
+    /// The fees set on `tx`, if any.
+    pub fn of(tx: &TypedTransaction) -> Option<Fees> {
+        match tx {
+            TypedTransaction::Eip1559(request) => Some(Fees::Eip1559 { 
+                max_fee_per_gas: request.max_fee_per_gas?, 
+                max_priority_fee_per_gas: request.max_priority_fee_per_gas? }),
+            tx => tx.gas_price().map(|gas_price| Fees::Legacy { gas_price: gas_price })
+        }
+    }
+
+    /// Fees for a transaction replacing one sent with these fees. Nodes only accept a replacement if every fee is
+    /// bumped by at least `REPLACEMENT_FEE_BUMP_PERCENT`. `current` are the current network fees, used if higher.
+    pub fn replacement(&self, current: &Fees) -> Fees {
+        match (self, current) {
+            (Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas }, 
+             Fees::Eip1559 { max_fee_per_gas: current_max_fee, max_priority_fee_per_gas: current_priority_fee }) => {
+                let max_priority_fee_per_gas = bump(*max_priority_fee_per_gas).max(*current_priority_fee);
+                let max_fee_per_gas = bump(*max_fee_per_gas).max(*current_max_fee).max(max_priority_fee_per_gas);
+                Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas }
+            },
+            (Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas }, current) => {
+                let max_priority_fee_per_gas = bump(*max_priority_fee_per_gas);
+                let max_fee_per_gas = bump(*max_fee_per_gas).max(current.max_gas_price()).max(max_priority_fee_per_gas);
+                Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas }
+            },
+            (Fees::Legacy { gas_price }, current) => Fees::Legacy { gas_price: bump(*gas_price).max(current.max_gas_price()) }
+        }
+    }
+
+    /// Convert `tx` into the transaction type matching the fees and apply them.
+    pub fn apply(&self, tx: TypedTransaction) -> TypedTransaction {
+        match self {
+            Fees::Legacy { gas_price } => {
+                let request: TransactionRequest = tx.into();
+                request.gas_price(*gas_price).into()
+            },
+            Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas } => {
+                let mut request = match tx {
+                    TypedTransaction::Eip1559(request) => request,
+                    tx => {
+                        let legacy: TransactionRequest = tx.into();
+                        Eip1559TransactionRequest {
+                            from: legacy.from,
+                            to: legacy.to,
+                            gas: legacy.gas,
+                            value: legacy.value,
+                            data: legacy.data,
+                            nonce: legacy.nonce,
+                            chain_id: legacy.chain_id,
+                            ..Default::default() }
+                    }
+                };
+                request.max_fee_per_gas = Some(*max_fee_per_gas);
+                request.max_priority_fee_per_gas = Some(*max_priority_fee_per_gas);
+                request.into()
+            }
+        }
+    }
+}
+
+/// The maximum fee `tx` can pay, if gas limit and fees are set.
+pub fn max_fee(tx: &TypedTransaction) -> Option<U256> {
+    Some(*tx.gas()? * Fees::of(tx)?.max_gas_price())
+}
+
+/// Minimum fee increase (percent) of a replacement transaction (geth's default price bump).
+const REPLACEMENT_FEE_BUMP_PERCENT: u64 = 10;
+
+/// `fee` increased by `REPLACEMENT_FEE_BUMP_PERCENT`, rounded up.
+fn bump(fee: U256) -> U256 {
+    (fee * (100 + REPLACEMENT_FEE_BUMP_PERCENT) + 99) / 100
+}
+
+/// Returns `true` if the latest block has a base fee, i.e. if the chain supports EIP-1559 transactions.
+pub async fn supports_eip1559(provider: &RpcProvider) -> Result<bool> {
+    let block = provider.get_block(BlockNumber::Latest).await?;
+    Ok(block.map_or(false, |block| block.base_fee_per_gas.is_some()))
+}
+
+/// Fetch the current fees from the node.
+pub async fn current_fees(provider: &RpcProvider, eip1559: bool) -> Result<Fees> {
+    if eip1559 {
+        let (max_fee_per_gas, max_priority_fee_per_gas) = provider.estimate_eip1559_fees(None).await?;
+        return Ok(Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas });
+    }
+    Ok(Fees::Legacy { gas_price: provider.get_gas_price().await? })
+}
+
+/// Number of blocks used when calculating fee presets.
+const FEE_HISTORY_BLOCK_COUNT: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeeSpeed {
+    Slow,
+    Normal,
+    Fast
+}
+
+impl FeeSpeed {
+
+    pub const ALL: [FeeSpeed; 3] = [FeeSpeed::Slow, FeeSpeed::Normal, FeeSpeed::Fast];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeeSpeed::Slow => "slow",
+            FeeSpeed::Normal => "normal",
+            FeeSpeed::Fast => "fast"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FeeSpeed> {
+        FeeSpeed::ALL.iter().find(|speed| speed.name() == name).copied()
+    }
+
+    /// Priority fee percentile (of the recent blocks) used for EIP-1559 chains.
+    fn reward_percentile(&self) -> f64 {
+        match self {
+            FeeSpeed::Slow => 10.0,
+            FeeSpeed::Normal => 50.0,
+            FeeSpeed::Fast => 90.0
+        }
+    }
+
+    /// Percentage of the node's gas price used for legacy chains.
+    fn gas_price_percentage(&self) -> u64 {
+        match self {
+            FeeSpeed::Slow => 90,
+            FeeSpeed::Normal => 100,
+            FeeSpeed::Fast => 125
+        }
+    }
+}
+
+/// Slow, normal and fast fees. Uses the `eth_feeHistory` percentiles on EIP-1559 chains.
+pub async fn fee_presets(provider: &RpcProvider, eip1559: bool) -> Result<Vec<(FeeSpeed, Fees)>> {
+
+    if !eip1559 {
+        let gas_price = provider.get_gas_price().await?;
+        return Ok(FeeSpeed::ALL.iter()
+            .map(|speed| (*speed, Fees::Legacy { gas_price: gas_price * speed.gas_price_percentage() / 100 }))
+            .collect());
+    }
+
+    let percentiles: Vec<f64> = FeeSpeed::ALL.iter().map(|speed| speed.reward_percentile()).collect();
+    let history = provider.fee_history(FEE_HISTORY_BLOCK_COUNT, BlockNumber::Latest, &percentiles).await?;
+    // The last entry is the base fee of the next block.
+    let base_fee = history.base_fee_per_gas.last().copied().unwrap_or_default();
+
+    Ok(FeeSpeed::ALL.iter().enumerate().map(|(index, speed)| {
+        let rewards: Vec<U256> = history.reward.iter().filter_map(|rewards| rewards.get(index)).copied().collect();
+        let max_priority_fee_per_gas = if rewards.is_empty() { 
+            U256::zero() 
+        } else { 
+            rewards.iter().fold(U256::zero(), |sum, reward| sum + reward) / rewards.len() 
+        };
+        // Leave room for the base fee to double before the transaction is included.
+        let max_fee_per_gas = base_fee * 2 + max_priority_fee_per_gas;
+        (*speed, Fees::Eip1559 { max_fee_per_gas, max_priority_fee_per_gas })
+    }).collect())
+}
+
+/// An unsigned transaction with its estimated gas limit and the fee alternatives the user can choose from.
+#[derive(Debug, Clone)]
+pub struct TransactionPreview {
+    /// The transaction without fees.
+    pub tx: TypedTransaction,
+    pub gas_limit: U256,
+    /// Native amount transferred by the transaction (zero for token transfers).
+    pub value: U256,
+    /// Recipient and amount of the transferred asset (native or token).
+    pub recipient: Address,
+    pub amount: U256,
+    pub options: Vec<(FeeSpeed, Fees)>
+}
+
+impl TransactionPreview {
+
+    pub(crate) async fn new(provider: &RpcProvider, tx: TypedTransaction, recipient: Address, amount: U256, eip1559: bool) -> Result<Self> {
+        let gas_limit = provider.estimate_gas(&tx, None).await?;
+        let value = tx.value().copied().unwrap_or_default();
+        let options = fee_presets(provider, eip1559).await?;
+        Ok(Self { tx, gas_limit, value, recipient, amount, options })
+    }
+
+    pub fn fees(&self, speed: FeeSpeed) -> Option<Fees> {
+        self.options.iter().find(|(s, _)| *s == speed).map(|(_, fees)| *fees)
+    }
+
+    /// The maximum fee (in the native currency's base unit) paid using `fees`.
+    pub fn fee(&self, fees: &Fees) -> U256 {
+        self.gas_limit * fees.max_gas_price()
+    }
+
+    /// The maximum amount of the native currency debited using `fees`.
+    pub fn total(&self, fees: &Fees) -> U256 {
+        self.value + self.fee(fees)
+    }
+
+    /// The transaction to sign using the fees of `speed`.
+    pub fn transaction(&self, speed: FeeSpeed) -> Option<TypedTransaction> {
+        let fees = self.fees(speed)?;
+        let mut tx = self.tx.clone();
+        tx.set_gas(self.gas_limit);
+        Some(fees.apply(tx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use crate::mock;
+
+    fn eip1559(max_fee_per_gas: u64, max_priority_fee_per_gas: u64) -> Fees {
+        Fees::Eip1559 { max_fee_per_gas: U256::from(max_fee_per_gas), max_priority_fee_per_gas: U256::from(max_priority_fee_per_gas) }
+    }
+
+    fn legacy(gas_price: u64) -> Fees {
+        Fees::Legacy { gas_price: U256::from(gas_price) }
+    }
+
+    async fn provider<F>(respond: F) -> RpcProvider where F: Fn(&str, &Value) -> Value + Send + 'static {
+        let url = mock::serve_rpc(move |method, params| match method {
+            "eth_chainId" => json!("0x1"),
+            method => respond(method, params)
+        });
+        Provider::new(RpcClient::connect(&[url], None).await.unwrap())
+    }
+
+    #[test]
+    fn bumps_by_the_minimum_rounding_up() {
+        assert_eq!(bump(U256::from(100)), U256::from(110));
+        assert_eq!(bump(U256::from(101)), U256::from(112));
+        assert_eq!(bump(U256::one()), U256::from(2));
+        assert_eq!(bump(U256::zero()), U256::zero());
+    }
+
+    #[test]
+    fn replaces_with_bumped_fees() {
+        assert_eq!(legacy(100).replacement(&legacy(50)), legacy(110));
+        assert_eq!(eip1559(200, 10).replacement(&eip1559(100, 5)), eip1559(220, 11));
+    }
+
+    #[test]
+    fn replaces_with_current_fees_if_higher() {
+        assert_eq!(legacy(100).replacement(&legacy(150)), legacy(150));
+        assert_eq!(eip1559(200, 10).replacement(&eip1559(300, 20)), eip1559(300, 20));
+        assert_eq!(eip1559(200, 10).replacement(&legacy(250)), eip1559(250, 11));
+        assert_eq!(legacy(100).replacement(&eip1559(300, 20)), legacy(300));
+    }
+
+    #[test]
+    fn replacement_max_fee_covers_priority_fee() {
+        assert_eq!(eip1559(10, 10).replacement(&eip1559(0, 50)), eip1559(50, 50));
+    }
+
+    #[test]
+    fn replaces_zero_priority_fee() {
+        // Nothing to bump, but the max fee still has to increase.
+        assert_eq!(eip1559(200, 0).replacement(&eip1559(100, 0)), eip1559(220, 0));
+    }
+
+    #[test]
+    fn applies_fees() {
+        let tx: TypedTransaction = TransactionRequest::new().value(5).gas(21000).into();
+        let converted = eip1559(200, 10).apply(tx.clone());
+        assert!(matches!(converted, TypedTransaction::Eip1559(_)));
+        assert_eq!(Fees::of(&converted), Some(eip1559(200, 10)));
+        assert_eq!(converted.value(), Some(&U256::from(5)));
+        assert_eq!(max_fee(&converted), Some(U256::from(21000 * 200)));
+
+        let legacy_tx = legacy(30).apply(converted);
+        assert_eq!(Fees::of(&legacy_tx), Some(legacy(30)));
+        assert_eq!(max_fee(&legacy_tx), Some(U256::from(21000 * 30)));
+    }
+
+    #[tokio::test]
+    async fn eip1559_presets_use_reward_percentiles() {
+        let provider = provider(|method, _| match method {
+            "eth_feeHistory" => json!({
+                "oldestBlock": "0x10",
+                "baseFeePerGas": ["0x50", "0x60", "0x64"],
+                "gasUsedRatio": [0.5, 0.5],
+                "reward": [["0x0", "0x4", "0xa"], ["0x0", "0x6", "0x14"]]
+            }),
+            _ => Value::Null
+        }).await;
+        let presets = fee_presets(&provider, true).await.unwrap();
+
+        // Slow blocks included transactions without a tip.
+        assert_eq!(presets, vec![(FeeSpeed::Slow, eip1559(200, 0)),
+                                 (FeeSpeed::Normal, eip1559(205, 5)),
+                                 (FeeSpeed::Fast, eip1559(215, 15))]);
+    }
+
+    #[tokio::test]
+    async fn eip1559_presets_without_rewards() {
+        let provider = provider(|method, _| match method {
+            "eth_feeHistory" => json!({ "oldestBlock": "0x10", "baseFeePerGas": ["0x64"], "gasUsedRatio": [], "reward": [] }),
+            _ => Value::Null
+        }).await;
+        let presets = fee_presets(&provider, true).await.unwrap();
+        assert!(presets.iter().all(|(_, fees)| *fees == eip1559(200, 0)));
+    }
+
+    #[tokio::test]
+    async fn legacy_presets_scale_the_gas_price() {
+        let provider = provider(|method, _| match method {
+            "eth_gasPrice" => json!("0x64"),
+            _ => Value::Null
+        }).await;
+        let presets = fee_presets(&provider, false).await.unwrap();
+        assert_eq!(presets, vec![(FeeSpeed::Slow, legacy(90)), (FeeSpeed::Normal, legacy(100)), (FeeSpeed::Fast, legacy(125))]);
+    }
+}
@@ -0,0 +1,283 @@
+use std::sync::{Arc, Mutex};
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::StringRepresentation;
+use crate::transaction::*;
+
+/// Persists the transaction history of a chain and provides the time used for its timestamps.
+pub trait HistoryStore {
+    fn load(&self, key: &str) -> Option<String>;
+    fn save(&mut self, key: &str, value: &str);
+    /// Milliseconds since the Unix epoch.
+    fn now(&self) -> u64;
+}
+
+pub type SharedHistoryStore = Arc<Mutex<dyn HistoryStore + Send + Sync>>;
+
+impl std::fmt::Debug for dyn HistoryStore + Send + Sync {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "HistoryStore")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum Direction {
+    /// Sent by the wallet.
+    #[default]
+    Outgoing,
+    /// Received from another address.
+    Incoming
+}
+
+impl Direction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Outgoing => "outgoing",
+            Direction::Incoming => "incoming"
+        }
+    }
+}
+
+/// Oldest records are removed once a chain has more records than this.
+const MAX_RECORDS: usize = 1000;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionRecord {
+    pub hash: H256,
+    pub chain_id: String,
+    /// Chain id for native transfers, contract address for token transfers.
+    pub asset: String,
+    pub symbol: String,
+    pub from: Address,
+    pub to: Address,
+    /// In base units of the asset.
+    pub amount: U256,
+    /// Maximum fee when sent, the fee actually paid once included. In base units of the native currency.
+    pub fee: Option<U256>,
+    pub status: TransactionStatus,
+    pub block_number: Option<u64>,
+    #[serde(default)]
+    pub direction: Direction,
+    /// Milliseconds since the Unix epoch.
+    pub created_at: u64,
+    pub updated_at: u64
+}
+
+impl TransactionRecord {
+
+    /// A transaction sent by the wallet, `fee` is the estimated maximum.
+    pub fn sent(hash: H256, chain_id: String, asset: String, symbol: String, from: Address, to: Address, amount: U256, fee: Option<U256>) -> Self {
+        Self { hash: hash,
+               chain_id: chain_id,
+               asset: asset,
+               symbol: symbol,
+               from: from,
+               to: to,
+               amount: amount,
+               fee: fee,
+               status: TransactionStatus::Pending,
+               block_number: None,
+               direction: Direction::Outgoing,
+               created_at: 0,
+               updated_at: 0 }
+    }
+
+    /// A transfer to the wallet found in block `block_number`.
+    pub fn received(hash: H256, chain_id: String, asset: String, symbol: String, from: Address, to: Address, amount: U256, block_number: u64, confirmations: u64) -> Self {
+        Self { hash: hash,
+               chain_id: chain_id,
+               asset: asset,
+               symbol: symbol,
+               from: from,
+               to: to,
+               amount: amount,
+               fee: None,
+               status: TransactionStatus::Confirmed { block_number: block_number, confirmations: confirmations },
+               block_number: Some(block_number),
+               direction: Direction::Incoming,
+               created_at: 0,
+               updated_at: 0 }
+    }
+
+    /// Records are unique per hash, asset and direction (a transaction can move several assets).
+    fn same_transfer(&self, other: &TransactionRecord) -> bool {
+        self.hash == other.hash && self.asset == other.asset && self.direction == other.direction
+    }
+}
+
+#[derive(Debug, Default)]
+struct HistoryState {
+    records: Vec<TransactionRecord>,
+    store: Option<SharedHistoryStore>
+}
+
+/// Transactions of one address on one chain, shared by the chain and its tokens.
+#[derive(Debug, Clone)]
+pub struct TransactionHistory {
+    chain_id: u64,
+    address: Address,
+    state: Arc<Mutex<HistoryState>>
+}
+
+impl TransactionHistory {
+
+    pub fn new(chain_id: u64, address: Address) -> Self {
+        Self { chain_id: chain_id, address: address, state: Arc::new(Mutex::new(HistoryState::default())) }
+    }
+
+    pub fn chain_id(&self) -> u64 { self.chain_id }
+
+    /// Storage key of the history.
+    pub fn key(&self) -> String {
+        format!("{}_{}", self.chain_id, self.address.string_representation())
+    }
+
+    /// Persist the history in `store` and restore a previously persisted history.
+    pub fn set_store(&self, store: SharedHistoryStore) {
+        let records = store.lock().unwrap().load(&self.key())
+            .and_then(|serialized| serde_json::from_str::<Vec<TransactionRecord>>(&serialized).ok())
+            .unwrap_or_default();
+        let mut state = self.state.lock().unwrap();
+        state.records = records;
+        state.store = Some(store);
+    }
+
+    /// All records, oldest first.
+    pub fn records(&self) -> Vec<TransactionRecord> {
+        self.state.lock().unwrap().records.clone()
+    }
+
+    /// The record of a transaction sent by the wallet.
+    pub fn record(&self, hash: H256) -> Option<TransactionRecord> {
+        self.state.lock().unwrap().records.iter().find(|record| record.hash == hash && record.direction == Direction::Outgoing).cloned()
+    }
+
+    /// Record a transaction sent by the wallet. `fee` is the estimated maximum.
+    pub fn add_sent(&self, hash: H256, asset: String, symbol: String, to: Address, amount: U256, fee: Option<U256>) {
+        self.add(TransactionRecord::sent(hash, self.chain_id.to_string(), asset, symbol, self.address, to, amount, fee));
+    }
+
+    /// Record a transfer to the wallet. Returns the record, `None` if the transfer was already recorded.
+    pub fn add_received(&self, hash: H256, asset: String, symbol: String, from: Address, amount: U256, block_number: u64, confirmations: u64) -> Option<TransactionRecord> {
+        self.add_new(TransactionRecord::received(hash, self.chain_id.to_string(), asset, symbol, from, self.address, amount, block_number, confirmations))
+    }
+
+    /// Add a transaction unless it is already recorded. Returns the added record.
+    pub(crate) fn add_new(&self, record: TransactionRecord) -> Option<TransactionRecord> {
+        if self.state.lock().unwrap().records.iter().any(|existing| existing.same_transfer(&record)) {
+            return None;
+        }
+        self.add(record.clone());
+        self.state.lock().unwrap().records.iter().find(|existing| existing.same_transfer(&record)).cloned()
+    }
+
+    /// Add a transaction. `updated_at` is set to now, `created_at` too unless the transaction is already recorded,
+    /// in which case the record is replaced in place.
+    pub fn add(&self, mut record: TransactionRecord) {
+        let mut state = self.state.lock().unwrap();
+        let now = state.now();
+        record.updated_at = now;
+        match state.records.iter_mut().find(|existing| existing.same_transfer(&record)) {
+            Some(existing) => {
+                record.created_at = existing.created_at;
+                *existing = record;
+            },
+            None => {
+                record.created_at = now;
+                state.records.push(record);
+                if state.records.len() > MAX_RECORDS {
+                    let excess = state.records.len() - MAX_RECORDS;
+                    state.records.drain(..excess);
+                }
+            }
+        }
+        state.persist(&self.key());
+    }
+
+    /// Merge records from another source, i.e. an explorer, keeping their timestamps. Known records only take over
+    /// a final status the local record doesn't have yet. Returns the number of added records.
+    pub fn import(&self, records: Vec<TransactionRecord>) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let now = state.now();
+        let mut added = 0;
+        let mut changed = false;
+        for record in records {
+            match state.records.iter_mut().find(|existing| existing.same_transfer(&record)) {
+                Some(existing) => {
+                    if record.status.is_final() && existing.status != record.status && !matches!(existing.status, TransactionStatus::Confirmed { .. }) {
+                        existing.status = record.status;
+                        existing.block_number = record.block_number;
+                        existing.fee = record.fee.or(existing.fee);
+                        existing.updated_at = now;
+                        changed = true;
+                    }
+                },
+                None => {
+                    state.records.push(record);
+                    added += 1;
+                    changed = true;
+                }
+            }
+        }
+        if changed {
+            state.records.sort_by_key(|record| record.created_at);
+            if state.records.len() > MAX_RECORDS {
+                let excess = state.records.len() - MAX_RECORDS;
+                state.records.drain(..excess);
+            }
+            state.persist(&self.key());
+        }
+        added
+    }
+
+    /// Update the status of a transaction sent by the wallet. Unknown hashes are ignored.
+    pub fn update_status(&self, hash: H256, status: &TransactionStatus) {
+        self.update(hash, |record| {
+            record.status = *status;
+            if let Some(block_number) = status.block_number() {
+                record.block_number = Some(block_number);
+            } else if *status == TransactionStatus::Pending {
+                record.block_number = None;
+            }
+            true
+        });
+    }
+
+    /// Replace the estimated fee by the fee paid.
+    pub fn update_fee(&self, hash: H256, fee: U256) {
+        self.update(hash, |record| {
+            let changed = record.fee != Some(fee);
+            record.fee = Some(fee);
+            changed
+        });
+    }
+
+    /// Apply `change` to the record of `hash`. `change` returns `false` if nothing changed.
+    fn update<F>(&self, hash: H256, change: F) where F: FnOnce(&mut TransactionRecord) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = state.now();
+        if let Some(record) = state.records.iter_mut().find(|record| record.hash == hash && record.direction == Direction::Outgoing) {
+            if change(record) {
+                record.updated_at = now;
+                state.persist(&self.key());
+            }
+        }
+    }
+}
+
+impl HistoryState {
+
+    fn now(&self) -> u64 {
+        self.store.as_ref().map_or(0, |store| store.lock().unwrap().now())
+    }
+
+    fn persist(&self, key: &str) {
+        if let Some(store) = &self.store {
+            match serde_json::to_string(&self.records) {
+                Ok(serialized) => store.lock().unwrap().save(key, &serialized),
+                Err(error) => println!("Unable to serialize transaction history: {}", error)
+            }
+        }
+    }
+}
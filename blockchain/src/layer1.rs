@@ -2,137 +2,434 @@ use std::sync::{Mutex, Arc, Weak};
 use std::time::Duration;
 use ethers::abi::{Address};
 use ethers::prelude::*;
-use ethers::providers::{Provider, Http};
 use ethers::signers::{LocalWallet};
+use ethers::types::transaction::eip2718::TypedTransaction;
 
-use crate::ChainsMetadata;
+use crate::StringRepresentation;
+use crate::amount::denominator;
 use crate::chain::*;
+use crate::chain_metadata::*;
+use crate::explorer::*;
+use crate::fees::*;
+use crate::history::*;
+use crate::nonce::*;
+use crate::rpc::*;
+use crate::scanner::*;
 use crate::shared::*;
+use crate::subscription::*;
 use crate::token::*;
+use crate::transaction::*;
 
 #[derive(Clone)]
 pub struct Layer1 {
 	tokens: Vec<Token>,
-    client: Arc<crate::shared::Client>,
-    rpc: String,
-    decimals: u32,
-    symbol: String,
+    provider: RpcProvider,
+    address: Address,
+    client: Option<Arc<crate::shared::Client>>,
+    /** Name, native currency and explorers of the chain. */
+    info: ChainInfo,
     chain_id: u64,
+    /** `true` if the chain accepts EIP-1559 (type 2) transactions. */
+    eip1559: bool,
+    /** Number of confirmations after which a transaction is reported as confirmed. */
+    confirmation_target: u64,
+    nonces: NonceManager,
+    history: TransactionHistory,
+    scanner: IncomingTransferScanner,
+    /** Optional explorer API used to import past transactions. */
+    explorer: Option<ExplorerClient>,
+    /** Shared by the clones, so that a new balance subscription stops the running one. */
+    subscriptions: SubscriptionControl,
     erc_20_contract_source: String,
 	observers: ObserverList
 }
 
 impl std::fmt::Debug for Layer1 {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Chain {} [{}]", self.symbol, self.rpc)
+        write!(f, "Chain {} [{}]", self.info.symbol, self.rpc())
     }
 }
 
 impl TokenData for Layer1 {
-    fn symbol(&self) -> String { self.symbol.clone() }
-    fn decimals(&self) -> u32 { self.decimals }
+    fn symbol(&self) -> String { self.info.symbol.clone() }
+    fn decimals(&self) -> u32 { self.info.decimals }
     fn identifier(&self) -> String { format!("{}", self.chain_id) }
-    fn denomiator(&self) -> u128 {
-        let ten: u128 = 10;
-        ten.pow(self.decimals) as u128
-    }
+    fn denomiator(&self) -> U256 { denominator(self.info.decimals) }
 }
 
-const FALLBACK_DECIMAL_COUNT: u64 = 18;
-const FALLBACK_SYMBOL_NAME: &str = "<unknown>";
-
 impl Layer1 {
 
-    pub async fn new(rpc: String, 
+    /** Connect to the chain served by `rpcs`. Further endpoints are fallbacks of the first one. */
+    pub async fn new(rpcs: Vec<String>, 
                      wallet: LocalWallet, 
                      meta_data: &ChainsMetadata, 
                      erc_20_contract_source: String,
                      chain_id: Option<u64>) -> Result<Layer1> {
 
-        let provider = Provider::<Http>::try_from(&rpc)?.interval(Duration::from_millis(10));
-        let provider_chain_id = provider.get_chainid().await.expect("couldn't retrieve chain id").as_u64();
-        let chain_id = chain_id.unwrap_or(provider_chain_id);
+        let provider = Provider::new(RpcClient::connect(&rpcs, chain_id).await?).interval(Duration::from_millis(10));
+        let provider_chain_id = provider.get_chainid().await?.as_u64();
+        if let Some(expected) = chain_id.filter(|expected| *expected != provider_chain_id) {
+            return Err(Box::new(EndpointError::ChainIdMismatch { expected: expected, found: provider_chain_id }));
+        }
+        let chain_id = provider_chain_id;
         let wallet = wallet.clone().with_chain_id(chain_id);
+        let address = wallet.address();
+        let eip1559 = supports_eip1559(&provider).await?;
         
-        let client = SignerMiddleware::new(provider, wallet);
+        let client = SignerMiddleware::new(provider.clone(), wallet);
         
         Ok(Self { tokens: Vec::new(), 
-                  client: Arc::new(client), 
-                  rpc: rpc,
-                  decimals: meta_data.get_decimals(chain_id).unwrap_or(FALLBACK_DECIMAL_COUNT) as u32,
-                  symbol: meta_data.get_symbol(chain_id).unwrap_or(FALLBACK_SYMBOL_NAME).to_string(),
+                  provider: provider,
+                  address: address,
+                  client: Some(Arc::new(client)), 
+                  info: meta_data.info(chain_id),
                   chain_id: chain_id,
+                  eip1559: eip1559,
+                  confirmation_target: DEFAULT_CONFIRMATION_TARGET,
+                  nonces: NonceManager::new(chain_id, address),
+                  history: TransactionHistory::new(chain_id, address),
+                  scanner: IncomingTransferScanner::new(format!("{}_{}", chain_id, address.string_representation())),
+                  explorer: None,
+                  subscriptions: SubscriptionControl::default(),
                   erc_20_contract_source: erc_20_contract_source,
                   observers: Arc::new(Mutex::new(Vec::new())) 
                 })
     }
 
+    /** Name, native currency and explorers of the chain. */
+    pub fn info(&self) -> &ChainInfo {
+        &self.info
+    }
+
+    /** Replace the name, native currency and explorers of the chain, i.e. by user defined ones. */
+    pub fn set_info(&mut self, info: ChainInfo) {
+        self.info = info;
+    }
+
     /** Add token and return the number of decimals. */
     pub async fn add_token(&mut self, address: String, symbol: String, decimals: Option<u32>) -> Result<u32> {
+        let token = self.connect_token(address, symbol, decimals).await?;
+        let decimals = token.decimals();
+        self.insert_token(token);
+        Ok(decimals)
+    }
+
+    /** Connect the token of contract `address` and fetch its balance without adding it, see `insert_token`. */
+    pub async fn connect_token(&self, address: String, symbol: String, decimals: Option<u32>) -> Result<Token> {
         
-        let decimals = decimals.unwrap_or(self.decimals);
+        let decimals = decimals.unwrap_or(self.info.decimals);
         if let Ok(address) = address.parse::<Address>() {
-            let mut token = Token::new(address, symbol, decimals, self.client.clone(), self.erc_20_contract_source.clone());
+            let mut token = Token::new(
+                address, 
+                symbol, 
+                decimals, 
+                self.provider.clone(), 
+                self.address, 
+                self.client.clone(), 
+                self.eip1559,
+                self.nonces.clone(),
+                self.history.clone(),
+                self.erc_20_contract_source.clone());
 
             let observers = self.observers.lock();
             for observer in observers.unwrap().iter() {
                 token.subscribe(observer.to_owned());
             }
             token.update_balance().await?;
-            self.tokens.push(token);
-            return Ok(decimals);
+            return Ok(token);
         }
 
         Err(Box::new(ChainError::InvalidAddress(address)))
     }
 
+    /** Add a token connected by `connect_token`. */
+    pub fn insert_token(&mut self, token: Token) {
+        self.tokens.push(token);
+    }
+
+    /** Remove the token of contract `address` and detach its observers. */
+    pub fn remove_token(&mut self, address: &str) -> Result<Token> {
+        let contract = address.parse::<Address>().map_err(|_| ChainError::InvalidAddress(address.to_string()))?;
+        let index = self.tokens.iter().position(|token| token.address() == contract)
+            .ok_or(ChainError::UnknownToken(address.to_string()))?;
+        let token = self.tokens.remove(index);
+        token.detach_observers();
+        Ok(token)
+    }
+
+    /** Stop the balance subscription and detach the observers of the chain and its tokens, i.e. when the chain is removed.
+     Background work still holding a clone of the chain no longer notifies anyone. */
+    pub fn detach(&self) {
+        self.unsubscribe_balances();
+        self.observers.lock().unwrap().clear();
+        for token in self.tokens.iter() {
+            token.detach_observers();
+        }
+    }
+
     pub fn tokens(&self) -> &Vec<Token> { &self.tokens }
 
-    pub fn rpc(&self) -> &String { &self.rpc }
+    /** The first reachable endpoint. */
+    pub fn rpc(&self) -> String { self.rpcs().first().cloned().unwrap_or_default() }
+
+    /** All endpoints, in order of preference when healthy. */
+    pub fn rpcs(&self) -> Vec<String> { self.provider.as_ref().rpcs() }
+
+    pub fn rpc_health(&self) -> Vec<EndpointHealth> { self.provider.as_ref().health() }
+
+    /** Check reachability, latency, head block, sync status and client of all endpoints. */
+    pub async fn diagnose_rpcs(&self) -> Vec<RpcDiagnostics> { self.provider.as_ref().diagnose().await }
+
+    /** Add a fallback endpoint. It has to serve this chain. */
+    pub async fn add_rpc(&self, rpc: String) -> Result<()> {
+        self.provider.as_ref().add(&rpc, self.chain_id).await
+    }
+
+    /** Remove an endpoint. The last endpoint, or one needed for the quorum, can't be removed. */
+    pub fn remove_rpc(&self, rpc: &str) -> Result<()> {
+        self.provider.as_ref().remove(rpc)
+    }
+
+    pub fn quorum(&self) -> Option<usize> { self.provider.as_ref().quorum() }
+
+    /** Only trust balances and receipts `quorum` endpoints agree on. `None` (or 1) trusts the first answer.
+     Fails if there are fewer endpoints than `quorum`. */
+    pub fn set_quorum(&self, quorum: Option<usize>) -> Result<()> {
+        self.provider.as_ref().set_quorum(quorum)
+    }
+
+    pub fn address(&self) -> Address { self.address }
+
+    pub fn supports_eip1559(&self) -> bool { self.eip1559 }
+
+    pub fn confirmation_target(&self) -> u64 { self.confirmation_target }
+
+    /** Nonces and pending transactions of the account on this chain (shared with the tokens). */
+    pub fn nonces(&self) -> &NonceManager { &self.nonces }
+
+    /** Transactions of the account on this chain (shared with the tokens). */
+    pub fn history(&self) -> &TransactionHistory { &self.history }
+
+    /** Persist the history in `store`, restoring what was stored before. */
+    pub fn set_history_store(&self, store: SharedHistoryStore) {
+        self.history.set_store(store);
+    }
+
+    pub fn explorer_api(&self) -> Option<&ExplorerApi> {
+        self.explorer.as_ref().map(|explorer| explorer.api())
+    }
+
+    /** Set or remove the explorer API used by `import_history`. */
+    pub fn set_explorer_api(&mut self, api: Option<ExplorerApi>) {
+        self.explorer = api.map(ExplorerClient::new);
+    }
+
+    /** Import past native and token transfers of the account from the explorer API into the history.
+     Returns the number of transfers that weren't recorded yet. */
+    pub async fn import_history(&self) -> Result<usize> {
+        let explorer = self.explorer.as_ref().ok_or(ExplorerError::NotConfigured)?;
+        explorer.import(self.address, &self.info.symbol, &self.history).await
+    }
+
+    /** Persist the block scanning cursor in `store`, resuming from what was stored before. */
+    pub fn set_scan_cursor_store(&self, store: SharedHistoryStore) {
+        self.scanner.set_store(store);
+    }
+
+    /** Scan new blocks for transfers of the native currency and the tokens to the account. Found transfers are added
+     to the history, reported to `observer` and the affected balances are updated. Returns the number of transfers found. */
+    pub async fn scan_incoming(&self, observer: Option<Weak<Mutex<ReceivedObserver>>>) -> Result<usize> {
+        let tokens: Vec<ScannedToken> = self.tokens.iter()
+            .map(|token| ScannedToken { address: token.address(), identifier: token.identifier(), symbol: token.symbol() })
+            .collect();
+        let records = self.scanner.scan(&self.provider, self.address, &self.info.symbol, &tokens, &self.history, self.confirmation_target).await?;
+
+        if let Some(observer) = observer.and_then(|observer| observer.upgrade()) {
+            for record in records.iter() {
+                observer.lock().unwrap().transfer_received(record);
+            }
+        }
+        if records.iter().any(|record| record.asset == self.identifier()) {
+            self.update_balance().await?;
+        }
+        for token in self.tokens.iter().filter(|token| records.iter().any(|record| record.asset == token.identifier())) {
+            token.update_balance().await?;
+        }
+        Ok(records.len())
+    }
+
+    /** Persist the pending transaction queue in `store`, restoring what was stored before. */
+    pub fn set_pending_transaction_store(&self, store: SharedPendingTransactionStore) {
+        self.nonces.set_store(store);
+    }
+
+    pub fn set_confirmation_target(&mut self, confirmation_target: u64) {
+        self.confirmation_target = confirmation_target.max(1);
+    }
+
+    /** Follow a transaction (native or token) sent on this chain. */
+    pub fn tracker(&self, hash: H256) -> TransactionTracker {
+        TransactionTracker::new(self.provider.clone(), self.identifier(), hash, self.confirmation_target)
+            .with_history(self.history.clone())
+    }
+
+    /** Returns `true` if the signing key has been removed using `lock`. */
+    pub fn is_locked(&self) -> bool { self.client.is_none() }
+
+    /** Drop the signing client (and with it the private key) for this chain and its tokens. Balances can still be read. */
+    pub fn lock(&mut self) {
+        self.client = None;
+        for token in self.tokens.iter_mut() {
+            token.set_client(None);
+        }
+    }
+
+    /** Re-create the signing client using `wallet`. */
+    pub fn unlock(&mut self, wallet: LocalWallet) {
+        let wallet = wallet.with_chain_id(self.chain_id);
+        let client = Some(Arc::new(SignerMiddleware::new(self.provider.clone(), wallet)));
+        for token in self.tokens.iter_mut() {
+            token.set_client(client.clone());
+        }
+        self.client = client;
+    }
 
     pub async fn update_balance(&self) -> Result<()> {
-        let balance = self.client.provider().get_balance(self.client.address(), None).await?;
-        self.notify_observers(balance.as_u128());
+        let balance = self.balance().await?;
+        self.notify_observers(balance);
         Ok(())
     }
 
-    pub async fn transfer(&self, to: Address, amount: u64, from: Option<Address>) -> Result<()> {
+    /** Fetch the native balance without notifying the observers. */
+    pub async fn balance(&self) -> Result<U256> {
+        Ok(self.provider.get_balance(self.address, None).await?)
+    }
+
+    /** `true` if balance changes can be pushed, which requires a WebSocket endpoint. */
+    pub fn supports_subscriptions(&self) -> bool { self.websocket_rpc().is_some() }
+
+    /** The first WebSocket endpoint, used for subscriptions. */
+    pub fn websocket_rpc(&self) -> Option<String> {
+        self.rpcs().into_iter().find(|rpc| is_websocket(rpc))
+    }
 
-        let from: Address = from.unwrap_or(self.client.address());
-        println!("Transfer {amount} of {} to {}", self.symbol, to);
+    /** Subscription pushing balance changes of the chain and its current tokens to the observers, to be run in the
+     background. Stops the running subscription. `None` if the endpoint doesn't support subscriptions. */
+    pub fn subscribe_balances(&self) -> Option<BalanceSubscription> {
+        if !self.supports_subscriptions() { return None; }
+        Some(BalanceSubscription::new(self.clone(), self.subscriptions.clone()))
+    }
+
+    /** Stop the running balance subscription. */
+    pub fn unsubscribe_balances(&self) {
+        self.subscriptions.next();
+    }
+
+    /** Broadcast a transfer and return its hash without waiting for it to be mined. See `tracker`. */
+    pub async fn transfer(&self, to: Address, amount: U256, from: Option<Address>) -> Result<H256> {
+
+        let from: Address = from.unwrap_or(self.address);
         //let nonce1 = self.client.get_transaction_count(from, Some(BlockNumber::Latest.into())).await?;
-        let tx = TransactionRequest::new()
-            .to(to)
-            .from(from)
-            .value(amount);
-        println!("Sending transaction: {:?}", tx);
+        let tx = self.transfer_request(to, amount, from);
+        let tx = current_fees(&self.provider, self.eip1559).await?.apply(tx);
+        let hash = self.send_transaction(tx).await?;
+        self.record_sent(hash, self.identifier(), self.info.symbol.clone(), to, amount);
+        Ok(hash)
+    }
 
-        let _pending_tx = self.client.send_transaction(tx, None).await?.confirmations(5);
-        println!("Pending transaction: {:?}", _pending_tx);
-        let receipt_result = &_pending_tx.await?;
+    /** Estimate gas and fee alternatives for a transfer without sending it. */
+    pub async fn preview_transfer(&self, to: Address, amount: U256) -> Result<TransactionPreview> {
+        let tx = self.transfer_request(to, amount, self.address);
+        TransactionPreview::new(&self.provider, tx, to, amount, self.eip1559).await
+    }
 
-        println!("Receipt Result {:?}", &receipt_result);
-        
-        if let Some(receipt) = receipt_result {
-            let tx = self.client.get_transaction(receipt.transaction_hash).await?;
-            println!("Sent tx: {}\n", serde_json::to_string(&tx)?);
-            println!("Tx receipt: {}", serde_json::to_string(&receipt)?);
-            self.update_balance().await?;
-            return Ok(());
-        };
-        Err(Box::new(ChainError::NoReceipt))
+    /** Sign and send a previewed transaction using the fees of `speed`. */
+    pub async fn send_preview(&self, preview: &TransactionPreview, speed: FeeSpeed) -> Result<H256> {
+        let tx = preview.transaction(speed).ok_or(ChainError::InvalidFees)?;
+        let hash = self.send_transaction(tx).await?;
+        self.record_sent(hash, self.identifier(), self.info.symbol.clone(), preview.recipient, preview.amount);
+        Ok(hash)
     }
 
-     fn notify_observers(&self, gwei: u128) {
-        let observers = self.observers.lock();
-        for observer in observers.unwrap().iter() {
+    /** Resend a pending transaction (native or token) with fees high enough to replace it. Returns the new hash. */
+    pub async fn speed_up(&self, hash: H256) -> Result<H256> {
+        let client = self.client.as_ref().ok_or(ChainError::Locked)?;
+        let pending = self.replaceable_transaction(hash).await?;
+        let fees = self.replacement_fees(&pending.tx).await?;
+        let replacement = send_replacement(client, &self.nonces, fees.apply(pending.tx)).await?;
+        if let Some(record) = self.history.record(hash) {
+            self.record_sent(replacement, record.asset, record.symbol, record.to, record.amount);
+        }
+        Ok(replacement)
+    }
 
-            if let Some(observer) = observer.upgrade() {
-                observer.lock().unwrap().balance_updated(gwei, self);
-            } else {
-                panic!("Observer was nil. Have you cloned the Arc? This should be a cleanup");
-            }
+    /** Replace a pending transaction by a transfer of 0 to the own address. Returns the hash of the replacement.
+        The gas limit is estimated, a plain transfer costs more than 21000 on chains charging for L1 data. */
+    pub async fn cancel(&self, hash: H256) -> Result<H256> {
+        let client = self.client.as_ref().ok_or(ChainError::Locked)?;
+        let pending = self.replaceable_transaction(hash).await?;
+        let fees = self.replacement_fees(&pending.tx).await?;
+        let tx: TypedTransaction = TransactionRequest::new()
+            .to(self.address)
+            .from(self.address)
+            .value(0)
+            .nonce(pending.nonce)
+            .chain_id(self.chain_id)
+            .into();
+        let replacement = send_replacement(client, &self.nonces, fees.apply(tx)).await?;
+        self.record_sent(replacement, self.identifier(), self.info.symbol.clone(), self.address, U256::zero());
+        Ok(replacement)
+    }
+
+    fn record_sent(&self, hash: H256, asset: String, symbol: String, to: Address, amount: U256) {
+        let fee = self.nonces.pending_transaction(hash).and_then(|queued| max_fee(&queued.tx));
+        self.history.add_sent(hash, asset, symbol, to, amount, fee);
+    }
+
+    /** Identifier of the chain or the token a pending transaction was sent from. */
+    pub fn transaction_asset(&self, hash: H256) -> String {
+        let to = self.nonces.pending_transaction(hash).and_then(|pending| pending.tx.to_addr().copied());
+        match self.tokens.iter().find(|token| Some(token.address()) == to) {
+            Some(token) => token.identifier(),
+            None => self.identifier()
+        }
+    }
+
+    async fn replaceable_transaction(&self, hash: H256) -> Result<QueuedTransaction> {
+        let pending = self.nonces.pending_transaction(hash).ok_or(ChainError::UnknownTransaction(hash.string_representation()))?;
+        let mined = self.provider.get_transaction_count(self.address, Some(BlockNumber::Latest.into())).await?;
+        if mined > pending.nonce {
+            return Err(Box::new(ChainError::TransactionMined(hash.string_representation())));
         }
+        Ok(pending)
+    }
+
+    async fn replacement_fees(&self, tx: &TypedTransaction) -> Result<Fees> {
+        let eip1559 = matches!(tx, TypedTransaction::Eip1559(_));
+        let current = current_fees(&self.provider, eip1559).await?;
+        Ok(match Fees::of(tx) {
+            Some(fees) => fees.replacement(&current),
+            None => current
+        })
+    }
+
+    fn transfer_request(&self, to: Address, amount: U256, from: Address) -> TypedTransaction {
+        TransactionRequest::new()
+            .to(to)
+            .from(from)
+            .value(amount)
+            .chain_id(self.chain_id)
+            .into()
+    }
+
+    async fn send_transaction(&self, tx: TypedTransaction) -> Result<H256> {
+
+        let client = self.client.as_ref().ok_or(ChainError::Locked)?;
+        send_with_nonce(client, &self.nonces, tx).await
+    }
+
+    pub(crate) fn notify_observers(&self, balance: U256) {
+        notify(&self.observers, balance, self);
     }
 
 }
@@ -146,7 +443,10 @@ impl TokenInteractor for Layer1 {
         }
     }
 
-	fn unsubscribe(&mut self, _observer: &Arc<BalanceObserver>) {
-        panic!("Implement!");
+	fn unsubscribe(&mut self, observer: &Weak<Mutex<BalanceObserver>>) {
+        remove_observer(&self.observers, observer);
+        for token in self.tokens.iter_mut() {
+            token.unsubscribe(observer);
+        }
     }
 }
\ No newline at end of file
@@ -1,21 +1,31 @@
 //use std::borrow::BorrowMut;
 use std::sync::Mutex;
 use std::sync::{Arc, Weak};
-use ethers::types::Address;
+use ethers::types::{Address, H256};
 use ethers::utils::hex;
 
 pub mod account;
 pub mod chain;
 pub mod layer1;
 pub mod token;
+pub mod amount;
+pub mod fees;
+pub mod transaction;
+pub mod nonce;
+pub mod history;
+pub mod scanner;
+pub mod subscription;
+pub mod explorer;
 pub mod chain_metadata;
+pub mod rpc;
 pub mod shared;
 pub mod mnemonic;
+#[cfg(test)]
+mod mock;
 
 use crate::account::*;
 use crate::chain::*;
 use crate::layer1::*;
-use crate::chain_metadata::*;
 use crate::shared::*;
 
 pub trait StringRepresentation {
@@ -28,6 +38,12 @@ impl StringRepresentation for Address {
     }
 }
 
+impl StringRepresentation for H256 {
+    fn string_representation(&self) -> String {
+        format!("0x{}", hex::encode(self.as_bytes()))
+    }
+}
+
 pub struct Portfolio<A> where A: Account {
 
     chains: Arc<Mutex<Vec<Layer1>>>,
@@ -54,20 +70,39 @@ impl<A> Portfolio<A> where A: Account {
         }
     }
 
-    pub async fn update_balances(&self) -> Result<()> {
+    /** Remove the chain with id `chain_id` and detach its observers. Returns the removed chain. */
+    pub fn remove_chain(&mut self, chain_id: &str) -> Option<Layer1> {
+        let mut chains = self.chains.lock().unwrap();
+        let index = chains.iter().position(|chain| chain.identifier() == chain_id)?;
+        let chain = chains.remove(index);
+        chain.detach();
+        Some(chain)
+    }
 
-        let chains = self.chains.lock().unwrap();
-        for chain in chains.iter() {
-            
-            chain.update_balance().await?;
+    /** Remove the private keys from the owner and from all chains. Balances are still readable while locked. */
+    pub fn lock(&mut self) {
+        self.owner.lock().unwrap().lock();
+        for chain in self.chains.lock().unwrap().iter_mut() {
+            chain.lock();
+        }
+    }
 
-            for token in chain.tokens().iter() {
-                token.update_balance().await?;
-            }
+    /** Re-attach the owner's wallet to all chains. The owner needs to have been unlocked first. */
+    pub fn unlock(&mut self) -> Result<()> {
+        let wallet = self.owner.lock().unwrap().wallet().ok_or(ChainError::Locked)?;
+        for chain in self.chains.lock().unwrap().iter_mut() {
+            chain.unlock(wallet.clone());
         }
         Ok(())
     }
 
+    /** Update the balances of all chains and tokens. A failure doesn't stop the others, the first one is returned.
+     The returned future works on clones of the chains, it doesn't borrow the portfolio or hold its lock. */
+    pub fn update_balances(&self) -> impl std::future::Future<Output = Result<()>> {
+        let chains = self.chains.lock().unwrap().clone();
+        async move { update_balances(&chains).await }
+    }
+
     pub fn chains(&self) -> Arc<Mutex<Vec<Layer1>>> {
         self.chains.clone()
     }
@@ -78,5 +113,27 @@ impl<A> Portfolio<A> where A: Account {
 
 }
 
+/** Update the balances of `chains` and their tokens, see `Portfolio::update_balances`. */
+async fn update_balances(chains: &[Layer1]) -> Result<()> {
+
+    let mut first_error = None;
+    for chain in chains.iter() {
+        
+        if let Err(error) = chain.update_balance().await {
+            first_error.get_or_insert(error);
+        }
+
+        for token in chain.tokens().iter() {
+            if let Err(error) = token.update_balance().await {
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(())
+    }
+}
+
 
 
@@ -0,0 +1,60 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+
+/// A request received by the mock server.
+pub struct MockRequest {
+    /// Path and query, i.e. "/api?module=account".
+    pub target: String,
+    pub body: String
+}
+
+/// Answer every request on a local port with the JSON returned by `respond`. Returns the server's base URL.
+pub fn serve<F>(respond: F) -> String where F: Fn(&MockRequest) -> String + Send + 'static {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let request = read_request(&mut stream);
+            let body = respond(&request);
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    url
+}
+
+/// Serve JSON-RPC requests, `respond` maps a method and its parameters to the result.
+pub fn serve_rpc<F>(respond: F) -> String where F: Fn(&str, &serde_json::Value) -> serde_json::Value + Send + 'static {
+    serve(move |request| {
+        let call: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        let result = respond(call["method"].as_str().unwrap_or_default(), &call["params"]);
+        serde_json::json!({ "jsonrpc": "2.0", "id": call["id"], "result": result }).to_string()
+    })
+}
+
+fn read_request(stream: &mut std::net::TcpStream) -> MockRequest {
+    let mut received = vec![];
+    let mut buffer = [0u8; 4096];
+    let header_end = loop {
+        if let Some(position) = received.windows(4).position(|window| window == b"\r\n\r\n") {
+            break position + 4;
+        }
+        let read = stream.read(&mut buffer).unwrap();
+        if read == 0 { break received.len(); }
+        received.extend_from_slice(&buffer[..read]);
+    };
+    let header = String::from_utf8_lossy(&received[..header_end]).to_string();
+    let content_length = header.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    while received.len() < header_end + content_length {
+        let read = stream.read(&mut buffer).unwrap();
+        if read == 0 { break; }
+        received.extend_from_slice(&buffer[..read]);
+    }
+    MockRequest { target: header.split_whitespace().nth(1).unwrap_or_default().to_string(),
+                  body: String::from_utf8_lossy(&received[header_end..]).to_string() }
+}
@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use ethers::prelude::*;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use serde::{Deserialize, Serialize};
+
+use crate::chain::*;
+use crate::rpc::*;
+use crate::shared::*;
+use crate::StringRepresentation;
+
+/// Persists the pending transaction queue of a `NonceManager`.
+pub trait PendingTransactionStore {
+    fn load(&self, key: &str) -> Option<String>;
+    fn save(&mut self, key: &str, value: &str);
+}
+
+pub type SharedPendingTransactionStore = Arc<Mutex<dyn PendingTransactionStore + Send + Sync>>;
+
+/// Consecutive checks after which a queued transaction the node doesn't know about is considered dropped.
+const MAX_UNKNOWN_CHECKS: u32 = 3;
+
+/// A transaction broadcast by this wallet that was not mined yet (as far as the wallet knows).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedTransaction {
+    pub hash: H256,
+    pub nonce: U256,
+    /// The request as sent (including nonce, gas and fees), kept to allow replacing the transaction.
+    pub tx: TypedTransaction
+}
+
+#[derive(Debug, Default)]
+struct NonceState {
+    /// Nonces handed out but not yet broadcast.
+    reserved: Vec<U256>,
+    pending: Vec<QueuedTransaction>,
+    /// Number of consecutive checks the node didn't know about a queued transaction, by hash.
+    unknown: HashMap<H256, u32>,
+    store: Option<SharedPendingTransactionStore>
+}
+
+/// Hands out nonces for one address on one chain without asking the node for every transaction,
+/// so that transfers sent in quick succession don't collide.
+#[derive(Debug, Clone)]
+pub struct NonceManager {
+    chain_id: u64,
+    address: Address,
+    state: Arc<Mutex<NonceState>>
+}
+
+impl std::fmt::Debug for dyn PendingTransactionStore + Send + Sync {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "PendingTransactionStore")
+    }
+}
+
+impl NonceManager {
+
+    pub fn new(chain_id: u64, address: Address) -> Self {
+        Self { chain_id: chain_id, address: address, state: Arc::new(Mutex::new(NonceState::default())) }
+    }
+
+    /// Storage key of the pending transaction queue.
+    pub fn key(&self) -> String {
+        format!("{}_{}", self.chain_id, self.address.string_representation())
+    }
+
+    /// Persist the queue in `store` and restore a previously persisted queue.
+    pub fn set_store(&self, store: SharedPendingTransactionStore) {
+        let pending = store.lock().unwrap().load(&self.key())
+            .and_then(|serialized| serde_json::from_str::<Vec<QueuedTransaction>>(&serialized).ok())
+            .unwrap_or_default();
+        let mut state = self.state.lock().unwrap();
+        state.pending = pending;
+        state.store = Some(store);
+    }
+
+    /// Transactions broadcast from this address that were not mined when last checked.
+    pub fn pending(&self) -> Vec<QueuedTransaction> {
+        self.state.lock().unwrap().pending.clone()
+    }
+
+    pub fn pending_transaction(&self, hash: H256) -> Option<QueuedTransaction> {
+        self.state.lock().unwrap().pending.iter().find(|pending| pending.hash == hash).cloned()
+    }
+
+    /// Hand out the next nonce. Must be followed by `confirm` once broadcast or `release` if sending failed.
+    ///
+    /// The nonce is the lowest one from the node's pending count up that is neither queued nor reserved: past the queue if
+    /// the node hasn't seen it yet, in a gap if sending failed after later nonces were handed out. Queued transactions below
+    /// the node's mined count are removed. A queued transaction the node doesn't know about for `MAX_UNKNOWN_CHECKS`
+    /// consecutive checks (dropped, or reorged out and not re-added) is removed as well, so that its nonce is reused.
+    pub async fn reserve(&self, provider: &RpcProvider) -> Result<U256> {
+
+        let mined = provider.get_transaction_count(self.address, Some(BlockNumber::Latest.into())).await?;
+        let pool = provider.get_transaction_count(self.address, Some(BlockNumber::Pending.into())).await?;
+
+        // Queued transactions not included in the pending count either haven't reached the node yet or were dropped.
+        let uncounted: Vec<H256> = self.pending().iter().filter(|pending| pending.nonce >= pool).map(|pending| pending.hash).collect();
+        let mut unknown = vec![];
+        for hash in uncounted {
+            if provider.get_transaction(hash).await?.is_none() {
+                unknown.push(hash);
+            }
+        }
+
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let queued = state.pending.len();
+        state.pending.retain(|pending| pending.nonce >= mined);
+
+        state.unknown.retain(|hash, _| unknown.contains(hash));
+        for hash in unknown {
+            *state.unknown.entry(hash).or_insert(0) += 1;
+        }
+        let unknown = &state.unknown;
+        state.pending.retain(|pending| match unknown.get(&pending.hash) {
+            Some(checks) if *checks >= MAX_UNKNOWN_CHECKS => {
+                println!("Transaction with nonce {} on chain {} is unknown to the node, reusing its nonce.", pending.nonce, self.chain_id);
+                false
+            },
+            _ => true
+        });
+        let pending = &state.pending;
+        state.unknown.retain(|hash, _| pending.iter().any(|pending| pending.hash == *hash));
+        if state.pending.len() != queued {
+            state.persist(&self.key());
+        }
+
+        // Nonces reserved by transfers that are being sent right now aren't known to the node yet.
+        let mut nonce = pool;
+        while state.reserved.contains(&nonce) || state.pending.iter().any(|pending| pending.nonce == nonce) {
+            nonce += U256::one();
+        }
+        state.reserved.push(nonce);
+        Ok(nonce)
+    }
+
+    /// Record a broadcast transaction.
+    pub fn confirm(&self, nonce: U256, hash: H256, tx: TypedTransaction) {
+        let mut state = self.state.lock().unwrap();
+        state.reserved.retain(|reserved| *reserved != nonce);
+        state.pending.retain(|pending| pending.nonce != nonce || pending.hash == hash);
+        state.pending.push(QueuedTransaction { hash: hash, nonce: nonce, tx: tx });
+        state.persist(&self.key());
+    }
+
+    /// Return a nonce that wasn't used.
+    pub fn release(&self, nonce: U256) {
+        self.state.lock().unwrap().reserved.retain(|reserved| *reserved != nonce);
+    }
+
+    /// Remove a transaction from the queue, i.e. once mined or dropped.
+    pub fn remove(&self, hash: H256) {
+        let mut state = self.state.lock().unwrap();
+        state.pending.retain(|pending| pending.hash != hash);
+        state.persist(&self.key());
+    }
+}
+
+impl NonceState {
+
+    fn persist(&self, key: &str) {
+        if let Some(store) = &self.store {
+            match serde_json::to_string(&self.pending) {
+                Ok(serialized) => store.lock().unwrap().save(key, &serialized),
+                Err(error) => println!("Unable to serialize pending transactions: {}", error)
+            }
+        }
+    }
+}
+
+/// Fill `tx` (gas, fees) using a nonce from `nonces`, sign and broadcast it.
+pub(crate) async fn send_with_nonce(client: &Client, nonces: &NonceManager, mut tx: TypedTransaction) -> Result<H256> {
+
+    let nonce = nonces.reserve(client.inner()).await?;
+    tx.set_nonce(nonce);
+
+    let sent = async {
+        client.fill_transaction(&mut tx, None).await?;
+        let pending_tx = client.send_transaction(tx.clone(), None).await?;
+        Ok::<H256, Box<dyn std::error::Error + Send + Sync>>(pending_tx.tx_hash())
+    }.await;
+
+    match sent {
+        Ok(hash) => {
+            println!("Sent transaction {} with nonce {}", hash.string_representation(), nonce);
+            nonces.confirm(nonce, hash, tx);
+            Ok(hash)
+        },
+        Err(error) => {
+            nonces.release(nonce);
+            Err(error)
+        }
+    }
+}
+
+/// Broadcast `tx`, which reuses the nonce of a pending transaction, and replace that transaction in the queue.
+pub(crate) async fn send_replacement(client: &Client, nonces: &NonceManager, mut tx: TypedTransaction) -> Result<H256> {
+
+    let nonce = *tx.nonce().ok_or(ChainError::UnknownTransaction("replacement without nonce".to_string()))?;
+    client.fill_transaction(&mut tx, None).await?;
+    let hash = client.send_transaction(tx.clone(), None).await?.tx_hash();
+    println!("Sent replacement transaction {} with nonce {}", hash.string_representation(), nonce);
+    nonces.confirm(nonce, hash, tx);
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use crate::mock;
+
+    /// Transaction counts of the account and the transactions known to the mock node.
+    #[derive(Default)]
+    struct Node {
+        mined: u64,
+        pool: u64,
+        known: Vec<H256>
+    }
+
+    async fn provider(node: Arc<Mutex<Node>>) -> RpcProvider {
+        let url = mock::serve_rpc(move |method, params| {
+            let node = node.lock().unwrap();
+            match method {
+                "eth_chainId" => json!("0x1"),
+                "eth_getTransactionCount" if params[1] == "latest" => json!(format!("{:#x}", node.mined)),
+                "eth_getTransactionCount" => json!(format!("{:#x}", node.pool)),
+                "eth_getTransactionByHash" => {
+                    let hash: H256 = serde_json::from_value(params[0].clone()).unwrap();
+                    if node.known.contains(&hash) { transaction(hash) } else { Value::Null }
+                },
+                method => panic!("Unexpected call of {}", method)
+            }
+        });
+        Provider::new(RpcClient::connect(&[url], None).await.unwrap())
+    }
+
+    fn transaction(hash: H256) -> Value {
+        json!({ "hash": hash, "nonce": "0x0", "blockHash": null, "blockNumber": null, "transactionIndex": null,
+                "from": Address::zero(), "to": Address::zero(), "value": "0x0", "gasPrice": "0x1", "gas": "0x5208",
+                "input": "0x", "v": "0x1b", "r": "0x1", "s": "0x1" })
+    }
+
+    fn node(mined: u64, pool: u64) -> Arc<Mutex<Node>> {
+        Arc::new(Mutex::new(Node { mined: mined, pool: pool, known: vec![] }))
+    }
+
+    fn request() -> TypedTransaction {
+        TransactionRequest::new().into()
+    }
+
+    fn hash(nonce: u64) -> H256 {
+        H256::from_low_u64_be(nonce)
+    }
+
+    /// Queue a transaction with `nonce` that the node knows about.
+    fn broadcast(nonces: &NonceManager, node: &Arc<Mutex<Node>>, nonce: u64) {
+        nonces.confirm(U256::from(nonce), hash(nonce), request());
+        node.lock().unwrap().known.push(hash(nonce));
+    }
+
+    fn queued(nonces: &NonceManager) -> Vec<u64> {
+        nonces.pending().iter().map(|pending| pending.nonce.as_u64()).collect()
+    }
+
+    #[derive(Default)]
+    struct MemoryStore {
+        values: HashMap<String, String>
+    }
+
+    impl PendingTransactionStore for MemoryStore {
+        fn load(&self, key: &str) -> Option<String> { self.values.get(key).cloned() }
+        fn save(&mut self, key: &str, value: &str) { self.values.insert(key.to_string(), value.to_string()); }
+    }
+
+    #[tokio::test]
+    async fn starts_at_the_pending_count_of_the_node() {
+        let provider = provider(node(3, 5)).await;
+        let nonces = NonceManager::new(1, Address::zero());
+
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(5));
+    }
+
+    #[tokio::test]
+    async fn reserves_distinct_nonces_for_concurrent_sends() {
+        let provider = provider(node(5, 5)).await;
+        let nonces = NonceManager::new(1, Address::zero());
+
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(5));
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(6));
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(7));
+    }
+
+    #[tokio::test]
+    async fn continues_after_transactions_the_node_has_not_counted_yet() {
+        let node = node(5, 5);
+        let provider = provider(node.clone()).await;
+        let nonces = NonceManager::new(1, Address::zero());
+
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(5));
+        broadcast(&nonces, &node, 5);
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(6));
+        broadcast(&nonces, &node, 6);
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(7));
+        assert_eq!(queued(&nonces), vec![5, 6]);
+    }
+
+    #[tokio::test]
+    async fn reuses_a_released_nonce() {
+        let provider = provider(node(5, 5)).await;
+        let nonces = NonceManager::new(1, Address::zero());
+
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(5));
+        nonces.release(U256::from(5));
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(5));
+        assert!(nonces.pending().is_empty());
+    }
+
+    #[test]
+    fn replaces_the_queued_transaction_of_a_nonce() {
+        let nonces = NonceManager::new(1, Address::zero());
+
+        nonces.confirm(U256::from(5), hash(5), request());
+        nonces.confirm(U256::from(5), hash(50), request());
+        assert_eq!(nonces.pending().iter().map(|pending| pending.hash).collect::<Vec<H256>>(), vec![hash(50)]);
+        assert_eq!(nonces.pending_transaction(hash(5)).map(|pending| pending.nonce), None);
+    }
+
+    #[tokio::test]
+    async fn removes_mined_transactions() {
+        let node = node(5, 5);
+        let provider = provider(node.clone()).await;
+        let nonces = NonceManager::new(1, Address::zero());
+
+        broadcast(&nonces, &node, 5);
+        broadcast(&nonces, &node, 6);
+        broadcast(&nonces, &node, 7);
+        {
+            let mut node = node.lock().unwrap();
+            node.mined = 7;
+            node.pool = 8;
+        }
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(8));
+        assert_eq!(queued(&nonces), vec![7]);
+    }
+
+    #[tokio::test]
+    async fn reuses_the_nonce_of_a_dropped_transaction() {
+        let node = node(5, 5);
+        let provider = provider(node.clone()).await;
+        let nonces = NonceManager::new(1, Address::zero());
+
+        // The node dropped 5 and keeps 6 waiting for it.
+        nonces.confirm(U256::from(5), hash(5), request());
+        broadcast(&nonces, &node, 6);
+
+        for _ in 1..MAX_UNKNOWN_CHECKS {
+            let nonce = nonces.reserve(&provider).await.unwrap();
+            assert_eq!(nonce, U256::from(7));
+            nonces.release(nonce);
+        }
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(5));
+        assert_eq!(queued(&nonces), vec![6]);
+    }
+
+    #[tokio::test]
+    async fn keeps_transactions_the_node_knows_again() {
+        let node = node(5, 5);
+        let provider = provider(node.clone()).await;
+        let nonces = NonceManager::new(1, Address::zero());
+
+        // Not propagated to the node for a while.
+        nonces.confirm(U256::from(5), hash(5), request());
+        for _ in 1..MAX_UNKNOWN_CHECKS {
+            let nonce = nonces.reserve(&provider).await.unwrap();
+            nonces.release(nonce);
+        }
+        node.lock().unwrap().known.push(hash(5));
+        let nonce = nonces.reserve(&provider).await.unwrap();
+        nonces.release(nonce);
+        node.lock().unwrap().known.clear();
+
+        // The count starts over.
+        for _ in 1..MAX_UNKNOWN_CHECKS {
+            let nonce = nonces.reserve(&provider).await.unwrap();
+            assert_eq!(nonce, U256::from(6));
+            nonces.release(nonce);
+        }
+        assert_eq!(queued(&nonces), vec![5]);
+    }
+
+    #[tokio::test]
+    async fn restores_the_persisted_queue() {
+        let store: SharedPendingTransactionStore = Arc::new(Mutex::new(MemoryStore::default()));
+        let nonces = NonceManager::new(1, Address::zero());
+        nonces.set_store(store.clone());
+        nonces.confirm(U256::from(5), hash(5), request());
+        nonces.confirm(U256::from(6), hash(6), request());
+        nonces.remove(hash(5));
+
+        let restored = NonceManager::new(1, Address::zero());
+        restored.set_store(store);
+        assert_eq!(queued(&restored), vec![6]);
+        assert_eq!(restored.pending_transaction(hash(6)).map(|pending| pending.nonce), Some(U256::from(6)));
+    }
+
+    #[tokio::test]
+    async fn reuses_a_released_nonce_below_a_broadcast_one() {
+        let node = node(5, 5);
+        let provider = provider(node.clone()).await;
+        let nonces = NonceManager::new(1, Address::zero());
+
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(5));
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(6));
+        nonces.release(U256::from(5));
+        // The node keeps 6 in its future queue, its counts stay at 5 until the gap is filled.
+        broadcast(&nonces, &node, 6);
+
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(5));
+        broadcast(&nonces, &node, 5);
+        node.lock().unwrap().pool = 7;
+        assert_eq!(nonces.reserve(&provider).await.unwrap(), U256::from(7));
+    }
+}
@@ -0,0 +1,519 @@
+use std::error::Error;
+use std::fmt::{self, Debug};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use async_trait::async_trait;
+use ethers::providers::{Http, JsonRpcClient, Provider, ProviderError, RpcError, Ws};
+use ethers::types::{Block, SyncingStatus, H256, U256};
+use futures::future::join_all;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::shared::*;
+
+/// Reconnect attempts of the request connection of a WebSocket endpoint before requests fail.
+const WS_RECONNECTS: usize = 5;
+/// One failure of every endpoint is forgiven after this many requests, so that recovered endpoints are used again.
+const FAILURE_DECAY_REQUESTS: usize = 50;
+/// Requests whose answer is only trusted once the quorum of endpoints agrees.
+const QUORUM_METHODS: [&str; 3] = ["eth_getBalance", "eth_call", "eth_getTransactionReceipt"];
+/// Receipt fields compared for the quorum. Nodes may add client specific fields.
+const RECEIPT_QUORUM_FIELDS: [&str; 5] = ["transactionHash", "blockHash", "blockNumber", "status", "gasUsed"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EndpointError {
+    NoEndpoints,
+    Duplicate(String),
+    /// The only endpoint of a chain can't be removed.
+    LastEndpoint,
+    UnknownEndpoint(String),
+    ChainIdMismatch { expected: u64, found: u64 },
+    /// A quorum can't require more endpoints than there are.
+    QuorumTooHigh { quorum: usize, endpoints: usize }
+}
+
+impl Error for EndpointError { }
+
+impl fmt::Display for EndpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EndpointError::NoEndpoints => write!(f, "No reachable RPC endpoint."),
+            EndpointError::Duplicate(rpc) => write!(f, "RPC endpoint {rpc} is already used."),
+            EndpointError::LastEndpoint => write!(f, "The last RPC endpoint of a chain can't be removed."),
+            EndpointError::UnknownEndpoint(rpc) => write!(f, "Unknown RPC endpoint {rpc}."),
+            EndpointError::ChainIdMismatch { expected, found } => write!(f, "RPC endpoint serves chain id {found} instead of {expected}."),
+            EndpointError::QuorumTooHigh { quorum, endpoints } => write!(f, "A quorum of {quorum} exceeds the {endpoints} RPC endpoints of the chain.")
+        }
+    }
+}
+
+/// `true` for `ws://` and `wss://` endpoints.
+pub fn is_websocket(rpc: &str) -> bool {
+    let rpc = rpc.trim_start().to_ascii_lowercase();
+    rpc.starts_with("ws://") || rpc.starts_with("wss://")
+}
+
+#[derive(Debug, Clone)]
+enum Transport {
+    Http(Http),
+    Ws(Ws)
+}
+
+impl Transport {
+
+    async fn connect(rpc: &str) -> Result<Self> {
+        if is_websocket(rpc) {
+            Ok(Transport::Ws(Ws::connect_with_reconnects(rpc.trim(), WS_RECONNECTS).await?))
+        } else {
+            Ok(Transport::Http(Http::from_str(rpc.trim())?))
+        }
+    }
+
+    async fn request(&self, method: &str, params: &Value) -> std::result::Result<Value, ProviderError> {
+        match self {
+            Transport::Http(client) => client.request(method, params).await.map_err(Into::into),
+            Transport::Ws(client) => client.request(method, params).await.map_err(Into::into)
+        }
+    }
+
+    async fn typed_request<R: DeserializeOwned>(&self, method: &str, params: Value) -> std::result::Result<R, ProviderError> {
+        Ok(serde_json::from_value(self.request(method, &params).await?)?)
+    }
+
+    async fn chain_id(&self) -> std::result::Result<u64, ProviderError> {
+        Ok(self.typed_request::<U256>("eth_chainId", Value::Array(vec![])).await?.as_u64())
+    }
+
+    /// Check the endpoint. Only a failing `eth_chainId` makes it unreachable, other methods may be disabled.
+    async fn diagnose(&self, rpc: &str) -> RpcDiagnostics {
+        let mut diagnostics = RpcDiagnostics { rpc: rpc.to_string(), ..Default::default() };
+        let started = now();
+        match self.chain_id().await {
+            Ok(chain_id) => {
+                diagnostics.reachable = true;
+                diagnostics.latency_ms = Some((now() - started).max(0.0) as u64);
+                diagnostics.chain_id = Some(chain_id);
+            },
+            Err(error) => {
+                diagnostics.error = Some(error.to_string());
+                return diagnostics;
+            }
+        }
+
+        match self.typed_request::<Option<Block<H256>>>("eth_getBlockByNumber", serde_json::json!(["latest", false])).await {
+            Ok(Some(block)) => {
+                diagnostics.head_block = block.number.map(|number| number.as_u64());
+                diagnostics.head_block_age = Some(((now() / 1000.0) as u64).saturating_sub(block.timestamp.as_u64()));
+            },
+            Ok(None) => {},
+            Err(error) => diagnostics.error = Some(error.to_string())
+        }
+        if let Ok(status) = self.typed_request::<SyncingStatus>("eth_syncing", Value::Array(vec![])).await {
+            diagnostics.syncing = Some(!matches!(status, SyncingStatus::IsFalse));
+        }
+        diagnostics.client_version = self.typed_request::<String>("web3_clientVersion", Value::Array(vec![])).await.ok();
+        diagnostics
+    }
+}
+
+/// Result of checking an RPC endpoint.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcDiagnostics {
+    pub rpc: String,
+    pub reachable: bool,
+    /// Round trip of `eth_chainId`.
+    pub latency_ms: Option<u64>,
+    pub chain_id: Option<u64>,
+    pub head_block: Option<u64>,
+    /// Seconds since the head block was produced. A large age hints at a stalled node.
+    pub head_block_age: Option<u64>,
+    pub syncing: Option<bool>,
+    pub client_version: Option<String>,
+    /// The first failure, if any.
+    pub error: Option<String>
+}
+
+/// Connect to `rpc` and check it.
+pub async fn diagnose(rpc: &str) -> RpcDiagnostics {
+    match Transport::connect(rpc).await {
+        Ok(transport) => transport.diagnose(rpc).await,
+        Err(error) => RpcDiagnostics { rpc: rpc.to_string(), error: Some(error.to_string()), ..Default::default() }
+    }
+}
+
+/// Health of an endpoint as seen by the requests sent to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EndpointHealth {
+    pub rpc: String,
+    /// Failures since the last success, minus forgiven ones. Endpoints with fewer failures are tried first.
+    pub failures: u32,
+    pub requests: u64,
+    pub errors: u64
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    transport: Transport,
+    health: Mutex<EndpointHealth>,
+    /// Set once the endpoint is known to serve the chain of the client. Requests only go to verified endpoints.
+    verified: AtomicBool
+}
+
+impl Endpoint {
+
+    fn new(rpc: &str, transport: Transport, verified: bool) -> Self {
+        let health = EndpointHealth { rpc: rpc.to_string(), ..Default::default() };
+        Self { transport: transport, health: Mutex::new(health), verified: AtomicBool::new(verified) }
+    }
+
+    fn is_verified(&self) -> bool { self.verified.load(Ordering::Relaxed) }
+
+    fn succeeded(&self) {
+        let mut health = self.health.lock().unwrap();
+        health.requests += 1;
+        health.failures = 0;
+    }
+
+    fn failed(&self) {
+        let mut health = self.health.lock().unwrap();
+        health.requests += 1;
+        health.errors += 1;
+        health.failures += 1;
+    }
+
+    fn failures(&self) -> u32 { self.health.lock().unwrap().failures }
+
+    fn rpc(&self) -> String { self.health.lock().unwrap().rpc.clone() }
+}
+
+#[derive(Debug, Default)]
+struct RpcState {
+    endpoints: Mutex<Vec<Arc<Endpoint>>>,
+    /// The chain served by the endpoints, once known.
+    chain_id: Mutex<Option<u64>>,
+    /// Number of endpoints that need to agree on `QUORUM_METHODS`.
+    quorum: Mutex<Option<usize>>,
+    requests: AtomicUsize
+}
+
+/// JSON-RPC client of a chain over one or more endpoints (HTTP or WebSocket, selected by the URL scheme).
+/// Requests go to the healthiest endpoint and fail over to the next one on transport errors. In quorum mode
+/// balances and receipts are requested from all endpoints and only trusted if enough of them agree.
+/// Clones share endpoints and configuration.
+#[derive(Debug, Clone, Default)]
+pub struct RpcClient {
+    state: Arc<RpcState>
+}
+
+pub type RpcProvider = Provider<RpcClient>;
+
+impl RpcClient {
+
+    /// Connect to `rpcs` serving `chain_id`, or the chain of the first reachable endpoint. Endpoints serving another
+    /// chain are skipped. Endpoints whose chain id can't be checked are kept as unverified fallbacks in case they recover:
+    /// they aren't used before their chain id is checked, see `verify_endpoints`.
+    pub async fn connect(rpcs: &[String], chain_id: Option<u64>) -> Result<Self> {
+        let client = Self::default();
+        let mut chain_id = chain_id;
+        let mut mismatch = None;
+        for rpc in rpcs.iter() {
+            let transport = match Transport::connect(rpc).await {
+                Ok(transport) => transport,
+                Err(error) => {
+                    println!("Skipping RPC endpoint {}: {}", rpc, error);
+                    continue;
+                }
+            };
+            let endpoint = match (transport.chain_id().await, chain_id) {
+                (Ok(found), Some(expected)) if found != expected => {
+                    println!("Skipping RPC endpoint {}: serves chain id {}", rpc, found);
+                    mismatch = Some(EndpointError::ChainIdMismatch { expected: expected, found: found });
+                    continue;
+                },
+                (Ok(found), _) => {
+                    chain_id = Some(found);
+                    Endpoint::new(rpc, transport, true)
+                },
+                (Err(_), _) => {
+                    let endpoint = Endpoint::new(rpc, transport, false);
+                    endpoint.failed();
+                    endpoint
+                }
+            };
+            client.state.endpoints.lock().unwrap().push(Arc::new(endpoint));
+        }
+        // Without a chain id the endpoints can't be verified.
+        if client.state.endpoints.lock().unwrap().is_empty() || chain_id.is_none() {
+            return Err(Box::new(mismatch.unwrap_or(EndpointError::NoEndpoints)));
+        }
+        *client.state.chain_id.lock().unwrap() = chain_id;
+        Ok(client)
+    }
+
+    /// Connect to `rpc` and add it as a fallback. The endpoint has to be reachable and serve `chain_id`.
+    pub async fn add(&self, rpc: &str, chain_id: u64) -> Result<()> {
+        if self.rpcs().iter().any(|existing| existing == rpc) {
+            return Err(Box::new(EndpointError::Duplicate(rpc.to_string())));
+        }
+        let transport = Transport::connect(rpc).await?;
+        let found = transport.chain_id().await?;
+        if found != chain_id {
+            return Err(Box::new(EndpointError::ChainIdMismatch { expected: chain_id, found: found }));
+        }
+        self.state.endpoints.lock().unwrap().push(Arc::new(Endpoint::new(rpc, transport, true)));
+        Ok(())
+    }
+
+    /// `true` if there are unverified endpoints to check: before the first request, when no verified endpoint is left,
+    /// and whenever failures are forgiven.
+    fn needs_verification(&self) -> bool {
+        let endpoints = self.state.endpoints.lock().unwrap();
+        let unverified = endpoints.iter().filter(|endpoint| !endpoint.is_verified()).count();
+        unverified > 0 && (unverified == endpoints.len() || self.state.requests.load(Ordering::Relaxed) % FAILURE_DECAY_REQUESTS == 0)
+    }
+
+    /// Check the chain id of the unverified endpoints. Endpoints serving another chain are removed.
+    async fn verify_endpoints(&self) {
+        let expected = match *self.state.chain_id.lock().unwrap() {
+            Some(chain_id) => chain_id,
+            None => return
+        };
+        let unverified: Vec<Arc<Endpoint>> = self.state.endpoints.lock().unwrap().iter()
+            .filter(|endpoint| !endpoint.is_verified())
+            .cloned()
+            .collect();
+        for endpoint in unverified {
+            match endpoint.transport.chain_id().await {
+                Ok(found) if found == expected => endpoint.verified.store(true, Ordering::Relaxed),
+                Ok(found) => {
+                    println!("Removing RPC endpoint {}: serves chain id {}", endpoint.rpc(), found);
+                    self.state.endpoints.lock().unwrap().retain(|existing| !Arc::ptr_eq(existing, &endpoint));
+                },
+                Err(_) => endpoint.failed()
+            }
+        }
+    }
+
+    /// Check all endpoints.
+    pub async fn diagnose(&self) -> Vec<RpcDiagnostics> {
+        let endpoints = self.state.endpoints.lock().unwrap().clone();
+        join_all(endpoints.iter().map(|endpoint| async move { endpoint.transport.diagnose(&endpoint.rpc()).await })).await
+    }
+
+    pub fn remove(&self, rpc: &str) -> Result<()> {
+        let mut endpoints = self.state.endpoints.lock().unwrap();
+        let index = endpoints.iter().position(|endpoint| endpoint.rpc() == rpc)
+            .ok_or(EndpointError::UnknownEndpoint(rpc.to_string()))?;
+        if endpoints.len() == 1 {
+            return Err(Box::new(EndpointError::LastEndpoint));
+        }
+        if let Some(quorum) = self.quorum().filter(|quorum| *quorum > endpoints.len() - 1) {
+            return Err(Box::new(EndpointError::QuorumTooHigh { quorum: quorum, endpoints: endpoints.len() - 1 }));
+        }
+        endpoints.remove(index);
+        Ok(())
+    }
+
+    /// Endpoints in the order they were added.
+    pub fn rpcs(&self) -> Vec<String> {
+        self.state.endpoints.lock().unwrap().iter().map(|endpoint| endpoint.rpc()).collect()
+    }
+
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        self.state.endpoints.lock().unwrap().iter().map(|endpoint| endpoint.health.lock().unwrap().clone()).collect()
+    }
+
+    pub fn quorum(&self) -> Option<usize> { *self.state.quorum.lock().unwrap() }
+
+    /// Require `quorum` endpoints to agree on balances and receipts. `None` trusts the first answer.
+    /// The quorum can't exceed the number of endpoints.
+    pub fn set_quorum(&self, quorum: Option<usize>) -> Result<()> {
+        let endpoints = self.state.endpoints.lock().unwrap().len();
+        if let Some(quorum) = quorum.filter(|quorum| *quorum > endpoints) {
+            return Err(Box::new(EndpointError::QuorumTooHigh { quorum: quorum, endpoints: endpoints }));
+        }
+        *self.state.quorum.lock().unwrap() = quorum.filter(|quorum| *quorum > 1);
+        Ok(())
+    }
+
+    /// Verified endpoints ordered by health, ties in the order they were added.
+    fn ordered(&self) -> Vec<Arc<Endpoint>> {
+        let mut endpoints: Vec<Arc<Endpoint>> = self.state.endpoints.lock().unwrap().iter()
+            .filter(|endpoint| endpoint.is_verified())
+            .cloned()
+            .collect();
+        if self.state.requests.fetch_add(1, Ordering::Relaxed) % FAILURE_DECAY_REQUESTS == FAILURE_DECAY_REQUESTS - 1 {
+            for endpoint in endpoints.iter() {
+                let mut health = endpoint.health.lock().unwrap();
+                health.failures = health.failures.saturating_sub(1);
+            }
+        }
+        endpoints.sort_by_key(|endpoint| endpoint.failures());
+        endpoints
+    }
+
+    async fn failover_request(&self, endpoints: &[Arc<Endpoint>], method: &str, params: &Value) -> std::result::Result<Value, ProviderError> {
+        let mut last_error = None;
+        for endpoint in endpoints.iter() {
+            match endpoint.transport.request(method, params).await {
+                Ok(value) => {
+                    endpoint.succeeded();
+                    return Ok(value);
+                },
+                // The node answered, i.e. "nonce too low". Another node would answer the same.
+                Err(error) if error.as_error_response().is_some() => {
+                    endpoint.succeeded();
+                    return Err(error);
+                },
+                Err(error) => {
+                    endpoint.failed();
+                    last_error = Some(error);
+                }
+            }
+        }
+        Err(last_error.unwrap_or(ProviderError::CustomError(EndpointError::NoEndpoints.to_string())))
+    }
+
+    async fn quorum_request(&self, endpoints: &[Arc<Endpoint>], quorum: usize, method: &str, params: &Value) -> std::result::Result<Value, ProviderError> {
+        let answers = join_all(endpoints.iter().map(|endpoint| endpoint.transport.request(method, params))).await;
+        let mut agreed: Vec<(Value, Value, usize)> = vec![];
+        for (endpoint, answer) in endpoints.iter().zip(answers) {
+            match answer {
+                Ok(value) => {
+                    endpoint.succeeded();
+                    let key = quorum_key(method, &value);
+                    match agreed.iter_mut().find(|(existing, _, _)| *existing == key) {
+                        Some((_, _, count)) => *count += 1,
+                        None => agreed.push((key, value, 1))
+                    }
+                },
+                Err(error) if error.as_error_response().is_some() => endpoint.succeeded(),
+                Err(_) => endpoint.failed()
+            }
+        }
+
+        match agreed.into_iter().find(|(_, _, count)| *count >= quorum) {
+            Some((_, value, _)) => Ok(value),
+            // A receipt without quorum is treated as not known yet.
+            None if method == "eth_getTransactionReceipt" => Ok(Value::Null),
+            None => Err(ProviderError::CustomError(format!("Less than {quorum} RPC endpoints agree on {method}.")))
+        }
+    }
+}
+
+/// The part of an answer endpoints have to agree on.
+fn quorum_key(method: &str, value: &Value) -> Value {
+    match (method, value) {
+        ("eth_getTransactionReceipt", Value::Object(receipt)) => Value::Array(
+            RECEIPT_QUORUM_FIELDS.iter().map(|field| receipt.get(*field).cloned().unwrap_or(Value::Null)).collect()),
+        _ => value.clone()
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl JsonRpcClient for RpcClient {
+    type Error = ProviderError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> std::result::Result<R, ProviderError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let params = serde_json::to_value(params)?;
+        if self.needs_verification() {
+            self.verify_endpoints().await;
+        }
+        let endpoints = self.ordered();
+        let value = match self.quorum() {
+            Some(quorum) if QUORUM_METHODS.contains(&method) => self.quorum_request(&endpoints, quorum, method, &params).await?,
+            _ => self.failover_request(&endpoints, method, &params).await?
+        };
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::providers::Middleware;
+    use ethers::types::Address;
+    use serde_json::json;
+    use crate::mock;
+
+    /// An endpoint of chain 1 whose balances are `balance`. While `down` it answers with garbage.
+    fn endpoint(balance: u64, down: Arc<AtomicBool>) -> String {
+        mock::serve(move |request| {
+            if down.load(Ordering::Relaxed) {
+                return "Bad gateway".to_string();
+            }
+            let call: Value = serde_json::from_str(&request.body).unwrap();
+            let result = match call["method"].as_str().unwrap_or_default() {
+                "eth_chainId" => json!("0x1"),
+                "eth_getBalance" => json!(format!("{:#x}", balance)),
+                "eth_getTransactionReceipt" => json!({ "transactionHash": H256::zero(), "status": format!("{:#x}", balance) }),
+                method => panic!("Unexpected call of {}", method)
+            };
+            json!({ "jsonrpc": "2.0", "id": call["id"], "result": result }).to_string()
+        })
+    }
+
+    fn up() -> Arc<AtomicBool> {
+        Arc::new(AtomicBool::new(false))
+    }
+
+    async fn balance(client: &RpcClient) -> Result<U256> {
+        Ok(Provider::new(client.clone()).get_balance(Address::zero(), None).await?)
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_the_next_endpoint() {
+        let down = up();
+        let client = RpcClient::connect(&[endpoint(1, down.clone()), endpoint(2, up())], None).await.unwrap();
+        assert_eq!(balance(&client).await.unwrap(), U256::from(1));
+
+        down.store(true, Ordering::Relaxed);
+        assert_eq!(balance(&client).await.unwrap(), U256::from(2));
+        assert_eq!(client.health().iter().map(|health| health.failures).collect::<Vec<u32>>(), vec![1, 0]);
+
+        // The failed endpoint is tried last, even once it's back.
+        down.store(false, Ordering::Relaxed);
+        assert_eq!(balance(&client).await.unwrap(), U256::from(2));
+    }
+
+    #[tokio::test]
+    async fn fails_once_all_endpoints_are_down() {
+        let down = up();
+        let client = RpcClient::connect(&[endpoint(1, down.clone()), endpoint(1, down.clone())], None).await.unwrap();
+        down.store(true, Ordering::Relaxed);
+        assert!(balance(&client).await.is_err());
+        assert_eq!(client.health().iter().map(|health| health.errors).collect::<Vec<u64>>(), vec![1, 1]);
+    }
+
+    #[tokio::test]
+    async fn trusts_the_answer_of_the_quorum() {
+        let client = RpcClient::connect(&[endpoint(1, up()), endpoint(2, up()), endpoint(2, up())], None).await.unwrap();
+        client.set_quorum(Some(2)).unwrap();
+        assert_eq!(balance(&client).await.unwrap(), U256::from(2));
+
+        client.set_quorum(Some(3)).unwrap();
+        assert!(balance(&client).await.is_err());
+        // A receipt without quorum isn't known yet.
+        let receipt = Provider::new(client.clone()).get_transaction_receipt(H256::zero()).await.unwrap();
+        assert!(receipt.is_none());
+    }
+
+    #[tokio::test]
+    async fn quorum_is_limited_by_the_endpoints() {
+        let client = RpcClient::connect(&[endpoint(1, up()), endpoint(1, up())], None).await.unwrap();
+        assert!(client.set_quorum(Some(3)).is_err());
+        client.set_quorum(Some(2)).unwrap();
+        let rpcs = client.rpcs();
+        assert!(client.remove(&rpcs[1]).is_err());
+
+        client.set_quorum(None).unwrap();
+        client.remove(&rpcs[1]).unwrap();
+        assert_eq!(client.rpcs(), vec![rpcs[0].clone()]);
+    }
+}
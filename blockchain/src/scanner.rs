@@ -0,0 +1,260 @@
+use std::sync::{Arc, Mutex};
+use ethers::prelude::*;
+
+use crate::history::*;
+use crate::rpc::*;
+use crate::shared::*;
+
+/// Maximum number of blocks fetched by a single scan. Remaining blocks are scanned by the next call.
+const MAX_BLOCKS_PER_SCAN: u64 = 50;
+const TRANSFER_EVENT: &str = "Transfer(address,address,uint256)";
+
+pub trait IncomingTransferObserver {
+    fn transfer_received(&mut self, record: &TransactionRecord);
+}
+
+pub type ReceivedObserver = dyn IncomingTransferObserver + Send + Sync;
+
+/// A token followed by the scanner.
+pub(crate) struct ScannedToken {
+    pub address: Address,
+    pub identifier: String,
+    pub symbol: String
+}
+
+#[derive(Debug, Default)]
+struct ScanState {
+    /// Last scanned block.
+    cursor: Option<u64>,
+    scanning: bool,
+    store: Option<SharedHistoryStore>
+}
+
+/// Follows the blocks of a chain to find native and ERC-20 transfers to an address.
+/// Only blocks with enough confirmations are scanned, so found transfers are final.
+#[derive(Debug, Clone)]
+pub struct IncomingTransferScanner {
+    key: String,
+    state: Arc<Mutex<ScanState>>
+}
+
+impl IncomingTransferScanner {
+
+    pub fn new(key: String) -> Self {
+        Self { key: key, state: Arc::new(Mutex::new(ScanState::default())) }
+    }
+
+    /// Persist the cursor in `store` and resume from a previously persisted cursor.
+    pub fn set_store(&self, store: SharedHistoryStore) {
+        let cursor = store.lock().unwrap().load(&self.key).and_then(|cursor| cursor.parse::<u64>().ok());
+        let mut state = self.state.lock().unwrap();
+        state.cursor = cursor;
+        state.store = Some(store);
+    }
+
+    /// The last scanned block.
+    pub fn cursor(&self) -> Option<u64> {
+        self.state.lock().unwrap().cursor
+    }
+
+    /// Scan the blocks following the cursor. The first scan (without cursor) starts at the current block.
+    pub(crate) async fn scan(&self,
+                             provider: &RpcProvider,
+                             address: Address,
+                             native_symbol: &str,
+                             tokens: &[ScannedToken],
+                             history: &TransactionHistory,
+                             confirmation_target: u64) -> Result<Vec<TransactionRecord>> {
+
+        let cursor = {
+            let mut state = self.state.lock().unwrap();
+            if state.scanning { return Ok(vec![]); }
+            state.scanning = true;
+            state.cursor
+        };
+
+        let result = self.scan_range(provider, address, native_symbol, tokens, history, confirmation_target, cursor).await;
+
+        let mut state = self.state.lock().unwrap();
+        state.scanning = false;
+        let (records, scanned_to) = result?;
+        if scanned_to.is_some() && scanned_to != state.cursor {
+            state.cursor = scanned_to;
+            if let (Some(store), Some(cursor)) = (&state.store, scanned_to) {
+                store.lock().unwrap().save(&self.key, &cursor.to_string());
+            }
+        }
+        Ok(records)
+    }
+
+    async fn scan_range(&self,
+                        provider: &RpcProvider,
+                        address: Address,
+                        native_symbol: &str,
+                        tokens: &[ScannedToken],
+                        history: &TransactionHistory,
+                        confirmation_target: u64,
+                        cursor: Option<u64>) -> Result<(Vec<TransactionRecord>, Option<u64>)> {
+
+        let head = provider.get_block_number().await?.as_u64();
+        let safe_head = head.saturating_sub(confirmation_target.saturating_sub(1));
+        let from = cursor.map_or(safe_head, |cursor| cursor + 1);
+        if from > safe_head {
+            return Ok((vec![], cursor));
+        }
+        let to = safe_head.min(from + MAX_BLOCKS_PER_SCAN - 1);
+        let chain_id = history.chain_id().to_string();
+        // Recorded once the whole range is scanned, so a failed scan doesn't leave records nobody was notified of.
+        let mut found: Vec<TransactionRecord> = vec![];
+
+        for number in from..=to {
+            let block = match provider.get_block_with_txs(number).await? {
+                Some(block) => block,
+                None => continue
+            };
+            for tx in block.transactions.iter() {
+                if tx.to == Some(address) && tx.from != address && !tx.value.is_zero() {
+                    found.push(TransactionRecord::received(
+                        tx.hash, chain_id.clone(), chain_id.clone(), native_symbol.to_string(), tx.from, address, tx.value, number, head - number + 1));
+                }
+            }
+        }
+
+        if !tokens.is_empty() {
+            let filter = Filter::new()
+                .address(tokens.iter().map(|token| token.address).collect::<Vec<Address>>())
+                .event(TRANSFER_EVENT)
+                .topic2(H256::from(address))
+                .from_block(from)
+                .to_block(to);
+            for log in provider.get_logs(&filter).await? {
+                let token = match tokens.iter().find(|token| token.address == log.address) {
+                    Some(token) => token,
+                    None => continue
+                };
+                let (hash, block_number) = match (log.transaction_hash, log.block_number) {
+                    (Some(hash), Some(block_number)) => (hash, block_number.as_u64()),
+                    _ => continue
+                };
+                let from_address = match log.topics.get(1) {
+                    Some(topic) => Address::from(*topic),
+                    None => continue
+                };
+                // The amount is the only non-indexed field of a standard transfer event.
+                if log.data.len() != 32 { continue; }
+                let amount = U256::from_big_endian(&log.data);
+                found.push(TransactionRecord::received(
+                    hash, chain_id.clone(), token.identifier.clone(), token.symbol.clone(), from_address, address, amount, block_number, head - block_number + 1));
+            }
+        }
+
+        let records = found.into_iter().filter_map(|record| history.add_new(record)).collect();
+        Ok((records, Some(to)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use serde_json::{json, Value};
+    use crate::mock;
+
+    /// Head and blocks of the mock node. Blocks listed in `failing` can't be fetched.
+    #[derive(Default)]
+    struct Node {
+        head: u64,
+        blocks: HashMap<u64, Vec<Value>>,
+        failing: Vec<u64>
+    }
+
+    async fn provider(node: Arc<Mutex<Node>>) -> RpcProvider {
+        let url = mock::serve_rpc(move |method, params| {
+            let node = node.lock().unwrap();
+            match method {
+                "eth_chainId" => json!("0x1"),
+                "eth_blockNumber" => json!(format!("{:#x}", node.head)),
+                "eth_getBlockByNumber" => {
+                    let number = u64::from_str_radix(params[0].as_str().unwrap().trim_start_matches("0x"), 16).unwrap();
+                    if node.failing.contains(&number) { return json!("unavailable"); }
+                    json!({ "hash": H256::from_low_u64_be(number), "number": format!("{:#x}", number),
+                            "transactions": node.blocks.get(&number).cloned().unwrap_or_default() })
+                },
+                method => panic!("Unexpected call of {}", method)
+            }
+        });
+        Provider::new(RpcClient::connect(&[url], None).await.unwrap())
+    }
+
+    fn address() -> Address {
+        Address::from_low_u64_be(1)
+    }
+
+    fn transfer(hash: H256) -> Value {
+        json!({ "hash": hash, "nonce": "0x0", "blockHash": null, "blockNumber": null, "transactionIndex": null,
+                "from": Address::from_low_u64_be(2), "to": address(), "value": "0x64", "gasPrice": "0x1", "gas": "0x5208",
+                "input": "0x", "v": "0x1b", "r": "0x1", "s": "0x1" })
+    }
+
+    async fn scan(scanner: &IncomingTransferScanner, provider: &RpcProvider, history: &TransactionHistory) -> Result<Vec<H256>> {
+        let records = scanner.scan(provider, address(), "ETH", &[], history, 3).await?;
+        Ok(records.iter().map(|record| record.hash).collect())
+    }
+
+    #[tokio::test]
+    async fn starts_at_the_last_confirmed_block() {
+        let node = Arc::new(Mutex::new(Node { head: 100, ..Default::default() }));
+        node.lock().unwrap().blocks.insert(98, vec![transfer(H256::from_low_u64_be(1))]);
+        let provider = provider(node.clone()).await;
+        let scanner = IncomingTransferScanner::new("scan".to_string());
+        let history = TransactionHistory::new(1, address());
+
+        assert_eq!(scan(&scanner, &provider, &history).await.unwrap(), vec![H256::from_low_u64_be(1)]);
+        assert_eq!(scanner.cursor(), Some(98));
+        assert_eq!(history.records().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn reports_each_transfer_once() {
+        let node = Arc::new(Mutex::new(Node { head: 100, ..Default::default() }));
+        let provider = provider(node.clone()).await;
+        let scanner = IncomingTransferScanner::new("scan".to_string());
+        let history = TransactionHistory::new(1, address());
+        scan(&scanner, &provider, &history).await.unwrap();
+
+        // The first transfer is already known, i.e. imported from an explorer.
+        history.add_received(H256::from_low_u64_be(1), "1".to_string(), "ETH".to_string(), Address::from_low_u64_be(2), U256::from(100), 99, 5).unwrap();
+        {
+            let mut node = node.lock().unwrap();
+            node.head = 103;
+            node.blocks.insert(99, vec![transfer(H256::from_low_u64_be(1))]);
+            node.blocks.insert(100, vec![transfer(H256::from_low_u64_be(2))]);
+        }
+        assert_eq!(scan(&scanner, &provider, &history).await.unwrap(), vec![H256::from_low_u64_be(2)]);
+        assert_eq!(scanner.cursor(), Some(101));
+        assert_eq!(history.records().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rescans_a_failed_range() {
+        let node = Arc::new(Mutex::new(Node { head: 100, ..Default::default() }));
+        let provider = provider(node.clone()).await;
+        let scanner = IncomingTransferScanner::new("scan".to_string());
+        let history = TransactionHistory::new(1, address());
+        scan(&scanner, &provider, &history).await.unwrap();
+
+        {
+            let mut node = node.lock().unwrap();
+            node.head = 103;
+            node.blocks.insert(99, vec![transfer(H256::from_low_u64_be(1))]);
+            node.failing.push(100);
+        }
+        assert!(scan(&scanner, &provider, &history).await.is_err());
+        assert_eq!(scanner.cursor(), Some(98));
+        assert!(history.records().is_empty());
+
+        node.lock().unwrap().failing.clear();
+        assert_eq!(scan(&scanner, &provider, &history).await.unwrap(), vec![H256::from_low_u64_be(1)]);
+        assert_eq!(scanner.cursor(), Some(101));
+    }
+}
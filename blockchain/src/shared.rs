@@ -1,8 +1,18 @@
-use ethers::{prelude::{SignerMiddleware, k256}, providers::{Provider, Http}, signers::Wallet};
+use ethers::{prelude::{SignerMiddleware, k256}, signers::Wallet};
+
+use crate::rpc::RpcProvider;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
-pub (crate) type Client = SignerMiddleware<Provider<Http>, Wallet<k256::ecdsa::SigningKey>>;
+pub (crate) type Client = SignerMiddleware<RpcProvider, Wallet<k256::ecdsa::SigningKey>>;
+
+/// Milliseconds since the Unix epoch.
+pub fn now() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    return js_sys::Date::now();
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0.0, |elapsed| elapsed.as_millis() as f64);
+}
 
 #[allow(dead_code)]
 fn print_type_of<T>(_: &T) {
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use ethers::prelude::*;
+use futures::stream::{self, StreamExt};
+use futures_timer::Delay;
+
+use crate::chain::*;
+use crate::layer1::Layer1;
+use crate::rpc::EndpointError;
+use crate::shared::*;
+
+const TRANSFER_EVENT: &str = "Transfer(address,address,uint256)";
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Shared by the clones of a chain. Starting a subscription supersedes the previous one.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SubscriptionControl {
+    generation: Arc<AtomicUsize>
+}
+
+impl SubscriptionControl {
+
+    /// Stop the running subscription and return the generation of the next one.
+    pub(crate) fn next(&self) -> usize {
+        self.generation.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn is_current(&self, generation: usize) -> bool {
+        self.generation.load(Ordering::SeqCst) == generation
+    }
+}
+
+enum Event {
+    Head,
+    Transfer(Address)
+}
+
+/// Pushes balance changes of a chain and its tokens to the chain's observers, using `newHeads` and
+/// token `Transfer` log subscriptions over a WebSocket. Reconnects with exponential backoff.
+pub struct BalanceSubscription {
+    chain: Layer1,
+    control: SubscriptionControl,
+    generation: usize,
+    /// Last balance pushed, by chain id or token address.
+    balances: HashMap<String, U256>
+}
+
+impl BalanceSubscription {
+
+    pub(crate) fn new(chain: Layer1, control: SubscriptionControl) -> Self {
+        let generation = control.next();
+        Self { chain: chain, control: control, generation: generation, balances: HashMap::new() }
+    }
+
+    /// Run until the chain starts another subscription or `Layer1::unsubscribe_balances` is called.
+    pub async fn run(mut self) {
+        let mut delay = MIN_RECONNECT_DELAY;
+        while self.control.is_current(self.generation) {
+            match self.follow().await {
+                // Connected, the socket dropped afterwards.
+                Ok(true) => delay = MIN_RECONNECT_DELAY,
+                Ok(false) => return,
+                Err(error) => println!("Balance subscription of chain {} failed: {}", self.chain.identifier(), error)
+            }
+            if !self.control.is_current(self.generation) { return; }
+            Delay::new(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    /// Follow the chain over one connection. `Ok(true)` if the socket dropped, `Ok(false)` if superseded.
+    async fn follow(&mut self) -> Result<bool> {
+        let rpc = self.chain.websocket_rpc().ok_or(EndpointError::NoEndpoints)?;
+        let provider = Provider::<Ws>::connect(rpc.trim()).await?;
+        let address = self.chain.address();
+        let tokens: Vec<Address> = self.chain.tokens().iter().map(|token| token.address()).collect();
+
+        let heads = provider.subscribe_blocks().await?.map(|_| Event::Head);
+        let mut events: Vec<stream::LocalBoxStream<Event>> = vec![heads.boxed_local()];
+        if !tokens.is_empty() {
+            let filter = Filter::new().address(tokens.clone()).event(TRANSFER_EVENT);
+            let received = provider.subscribe_logs(&filter.clone().topic2(H256::from(address))).await?;
+            let sent = provider.subscribe_logs(&filter.topic1(H256::from(address))).await?;
+            events.push(received.map(|log| Event::Transfer(log.address)).boxed_local());
+            events.push(sent.map(|log| Event::Transfer(log.address)).boxed_local());
+        }
+        let mut events = stream::select_all(events);
+
+        // Catch up with what changed while disconnected.
+        self.push_chain_balance().await?;
+        for token in tokens {
+            self.push_token_balance(token).await?;
+        }
+
+        while let Some(event) = events.next().await {
+            if !self.control.is_current(self.generation) { return Ok(false); }
+            match event {
+                Event::Head => self.push_chain_balance().await?,
+                Event::Transfer(token) => self.push_token_balance(token).await?
+            }
+        }
+        Ok(true)
+    }
+
+    async fn push_chain_balance(&mut self) -> Result<()> {
+        let balance = self.chain.balance().await?;
+        if self.balances.insert(self.chain.identifier(), balance) != Some(balance) {
+            self.chain.notify_observers(balance);
+        }
+        Ok(())
+    }
+
+    async fn push_token_balance(&mut self, address: Address) -> Result<()> {
+        if let Some(token) = self.chain.tokens().iter().find(|token| token.address() == address) {
+            let balance = token.balance().await?;
+            if self.balances.insert(token.identifier(), balance) != Some(balance) {
+                token.notify_observers(balance);
+            }
+        }
+        Ok(())
+    }
+}
@@ -3,7 +3,12 @@ use ethers::abi::{Address, Abi};
 use ethers::prelude::*;
 
 use crate::{shared::*, StringRepresentation};
+use crate::amount::denominator;
 use crate::chain::*;
+use crate::fees::*;
+use crate::history::*;
+use crate::nonce::*;
+use crate::rpc::*;
 
 abigen!(
     ERC20Token,
@@ -15,7 +20,13 @@ pub struct Token  {
 	address: Address,
 	symbol: String,
     decimals: u32,
-    client: Arc<crate::shared::Client>,
+    provider: RpcProvider,
+    owner: Address,
+    client: Option<Arc<crate::shared::Client>>,
+    eip1559: bool,
+    /** Shared with the chain, so that native and token transfers don't reuse nonces. */
+    nonces: NonceManager,
+    history: TransactionHistory,
     contract_abi: Abi,
 	observers: ObserverList
 }
@@ -30,10 +41,7 @@ impl TokenData for Token {
     fn symbol(&self) -> String { self.symbol.clone() }
     fn decimals(&self) -> u32 { self.decimals }
     fn identifier(&self) -> String { self.address.string_representation() }
-    fn denomiator(&self) -> u128 {
-        let ten: u128 = 10;
-        ten.pow(self.decimals) as u128
-    }
+    fn denomiator(&self) -> U256 { denominator(self.decimals) }
 }
 
 impl Token {
@@ -41,71 +49,97 @@ impl Token {
     pub(crate) fn new(address: Address, 
                       symbol: String, 
                       decimals: u32, 
-                      client: Arc<crate::shared::Client>,
+                      provider: RpcProvider,
+                      owner: Address,
+                      client: Option<Arc<crate::shared::Client>>,
+                      eip1559: bool,
+                      nonces: NonceManager,
+                      history: TransactionHistory,
                       erc_20_contract_source: String) -> Token {
 
         Self {  address: address, 
                 symbol: symbol, 
                 decimals: decimals, 
+                provider: provider,
+                owner: owner,
                 client: client,
+                eip1559: eip1559,
+                nonces: nonces,
+                history: history,
                 contract_abi: serde_json::from_str(&*erc_20_contract_source).expect("Unable to parse ABI"),
                 observers: Arc::new(Mutex::new(Vec::new())) }
     }
 
-    fn notify_observers(&self, gwei: u128) {
-        println!("Notifying token-observers for gwei: {gwei}");
-        let observers = self.observers.lock().unwrap();
-        println!("Observer count: {}", observers.len());
-        for observer in observers.iter() {
-
-            if let Some(observer) = observer.upgrade() {
-                observer.lock().unwrap().balance_updated(gwei, self);
-            } else {
-                panic!("Observer was nil. Have you cloned the Arc? This should be a cleanup");
-            }
-        }
+    pub(crate) fn notify_observers(&self, balance: U256) {
+        notify(&self.observers, balance, self);
+    }
+
+    /** Stop notifying the observers, i.e. when the token is removed. Clones still running stay silent. */
+    pub(crate) fn detach_observers(&self) {
+        self.observers.lock().unwrap().clear();
     }
 
     pub fn address(&self) -> Address { self.address }
+
+    pub(crate) fn set_client(&mut self, client: Option<Arc<crate::shared::Client>>) {
+        self.client = client;
+    }
     
     pub async fn update_balance(&self) -> Result<()> {
 
         println!("Updating token balance for: {:?}", self);
         
-        let contract = Contract::new(self.address, self.contract_abi.clone(), Arc::new(self.client.clone())); 
-
-        let req_method = contract.method::<H160, u128>("balanceOf", self.client.address())?;
-
-        let amount = req_method.call().await?;
+        let amount = self.balance().await?;
         self.notify_observers(amount);
         Ok(())
     }
 
-    pub async fn transfer(&self, to: Address, amount: u64, _from: Option<Address>) -> Result<()> {
+    /** Fetch the balance of the owner without notifying the observers. */
+    pub async fn balance(&self) -> Result<U256> {
+        let contract = Contract::new(self.address, self.contract_abi.clone(), Arc::new(self.provider.clone())); 
+
+        let req_method = contract.method::<H160, U256>("balanceOf", self.owner)?;
+
+        Ok(req_method.call().await?)
+    }
+
+    /** Broadcast a transfer and return its hash without waiting for it to be mined. */
+    pub async fn transfer(&self, to: Address, amount: U256, _from: Option<Address>) -> Result<H256> {
         
-        let contract = ERC20Token::new(self.address, self.client.clone());
+        let client = self.client.clone().ok_or(ChainError::Locked)?;
+        let contract = ERC20Token::new(self.address, client.clone());
         // println!("------------------ send");
         // print_type_of(&contract);
         
-        let _function_call = contract.transfer(to, U256::from(amount));
-        println!("  * Function Call: {:?}", _function_call);
+        let mut _function_call = contract.transfer(to, amount);
+        _function_call.tx = current_fees(&self.provider, self.eip1559).await?.apply(_function_call.tx);
         // print_type_of(&_function_call);
 
-        let _pending_tx = _function_call.send().await?;
-        println!("  *   Pending transaction: {:?}", _pending_tx);
-        // print_type_of(&_pending_tx);
-        
-        let _transaction_receipt = _pending_tx.await;
-        println!("  *   Transaction receipt: {:?}", _transaction_receipt);
-        // print_type_of(&_transaction_receipt);
-        
-        match _transaction_receipt {
-            Ok(_) => {
-                self.update_balance().await?;
-                Ok(())
-            },
-            Err(error) => Err(Box::new(error))
-        }
+        let hash = send_with_nonce(&client, &self.nonces, _function_call.tx).await?;
+        self.record_sent(hash, to, amount);
+        Ok(hash)
+    }
+
+    /** Estimate gas and fee alternatives for a transfer without sending it. */
+    pub async fn preview_transfer(&self, to: Address, amount: U256) -> Result<TransactionPreview> {
+        let contract = ERC20Token::new(self.address, Arc::new(self.provider.clone()));
+        let mut tx = contract.transfer(to, amount).tx;
+        tx.set_from(self.owner);
+        TransactionPreview::new(&self.provider, tx, to, amount, self.eip1559).await
+    }
+
+    /** Sign and send a previewed transaction using the fees of `speed`. */
+    pub async fn send_preview(&self, preview: &TransactionPreview, speed: FeeSpeed) -> Result<H256> {
+        let client = self.client.clone().ok_or(ChainError::Locked)?;
+        let tx = preview.transaction(speed).ok_or(ChainError::InvalidFees)?;
+        let hash = send_with_nonce(&client, &self.nonces, tx).await?;
+        self.record_sent(hash, preview.recipient, preview.amount);
+        Ok(hash)
+    }
+
+    fn record_sent(&self, hash: H256, to: Address, amount: U256) {
+        let fee = self.nonces.pending_transaction(hash).and_then(|queued| max_fee(&queued.tx));
+        self.history.add_sent(hash, self.identifier(), self.symbol.clone(), to, amount, fee);
     }
 }
 
@@ -115,7 +149,7 @@ impl TokenInteractor for Token {
         self.observers.lock().unwrap().push(observer);
     }
 
-	fn unsubscribe(&mut self, _observer: &Arc<BalanceObserver>) {
-        panic!("Not implmented!");
+	fn unsubscribe(&mut self, observer: &Weak<Mutex<BalanceObserver>>) {
+        remove_observer(&self.observers, observer);
     }
 }
\ No newline at end of file
@@ -0,0 +1,181 @@
+use std::sync::{Mutex, Weak};
+use std::time::Duration;
+use ethers::prelude::*;
+use futures_timer::Delay;
+use serde::{Deserialize, Serialize};
+
+use crate::StringRepresentation;
+use crate::history::TransactionHistory;
+use crate::rpc::*;
+
+/// Number of confirmations after which a transaction is considered final unless configured otherwise.
+pub const DEFAULT_CONFIRMATION_TARGET: u64 = 5;
+const POLL_INTERVAL: Duration = Duration::from_secs(4);
+/// Number of consecutive polls a transaction may be unknown to the node before it's reported as dropped.
+const DROPPED_AFTER_POLLS: u32 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransactionStatus {
+    /// Broadcast, waiting in the mempool.
+    Pending,
+    /// Included in block `block_number` with `confirmations` of `target` confirmations.
+    Included { block_number: u64, confirmations: u64, target: u64 },
+    /// Included and reached the confirmation target.
+    Confirmed { block_number: u64, confirmations: u64 },
+    /// Included but reverted.
+    Failed { block_number: u64 },
+    /// Removed from the mempool or replaced by another transaction with the same nonce.
+    Dropped
+}
+
+impl TransactionStatus {
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionStatus::Pending => "pending",
+            TransactionStatus::Included { .. } => "included",
+            TransactionStatus::Confirmed { .. } => "confirmed",
+            TransactionStatus::Failed { .. } => "failed",
+            TransactionStatus::Dropped => "dropped"
+        }
+    }
+
+    /// `true` if the status won't change anymore.
+    pub fn is_final(&self) -> bool {
+        matches!(self, TransactionStatus::Confirmed { .. } | TransactionStatus::Failed { .. } | TransactionStatus::Dropped)
+    }
+
+    pub fn block_number(&self) -> Option<u64> {
+        match self {
+            TransactionStatus::Included { block_number, .. } |
+            TransactionStatus::Confirmed { block_number, .. } |
+            TransactionStatus::Failed { block_number } => Some(*block_number),
+            _ => None
+        }
+    }
+
+    pub fn confirmations(&self) -> u64 {
+        match self {
+            TransactionStatus::Included { confirmations, .. } |
+            TransactionStatus::Confirmed { confirmations, .. } => *confirmations,
+            _ => 0
+        }
+    }
+}
+
+pub trait TransactionStatusObserver {
+    /// `hash` is the 0x prefixed transaction hash.
+    fn status_changed(&mut self, chain_id: &str, hash: &str, status: &TransactionStatus);
+}
+
+pub type StatusObserver = dyn TransactionStatusObserver + Send + Sync;
+
+/// Follows a broadcast transaction until it's confirmed, failed or dropped.
+#[derive(Debug, Clone)]
+pub struct TransactionTracker {
+    provider: RpcProvider,
+    chain_id: String,
+    hash: H256,
+    confirmation_target: u64,
+    /// Kept current with the status and the fee paid.
+    history: Option<TransactionHistory>
+}
+
+impl TransactionTracker {
+
+    pub fn new(provider: RpcProvider, chain_id: String, hash: H256, confirmation_target: u64) -> Self {
+        Self { provider: provider, chain_id: chain_id, hash: hash, confirmation_target: confirmation_target.max(1), history: None }
+    }
+
+    pub fn with_history(mut self, history: TransactionHistory) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    pub fn hash(&self) -> H256 { self.hash }
+
+    pub fn chain_id(&self) -> &String { &self.chain_id }
+
+    /// Poll the node until the transaction reaches a final status, notifying `observer` on every change.
+    /// A transaction that was included but disappears again (reorg) goes back to pending.
+    pub async fn track(&self, observer: Option<Weak<Mutex<StatusObserver>>>) -> TransactionStatus {
+
+        let mut status = TransactionStatus::Pending;
+        self.notify(&observer, &status);
+
+        // Sender and nonce, used to detect a replacement of the transaction.
+        let mut sender_nonce: Option<(Address, U256)> = None;
+        let mut unknown_polls: u32 = 0;
+
+        loop {
+            Delay::new(POLL_INTERVAL).await;
+
+            let next = match self.poll(&mut sender_nonce, &mut unknown_polls).await {
+                Ok(next) => next,
+                Err(error) => {
+                    println!("Unable to poll transaction {:?}: {}", self.hash, error);
+                    continue;
+                }
+            };
+
+            if next != status {
+                status = next;
+                self.notify(&observer, &status);
+            }
+            if status.is_final() {
+                return status;
+            }
+        }
+    }
+
+    async fn poll(&self, sender_nonce: &mut Option<(Address, U256)>, unknown_polls: &mut u32) -> Result<TransactionStatus, ProviderError> {
+
+        if let Some(receipt) = self.provider.get_transaction_receipt(self.hash).await? {
+            if let Some(block_number) = receipt.block_number {
+                let block_number = block_number.as_u64();
+                if let (Some(history), Some(gas_used), Some(gas_price)) = (&self.history, receipt.gas_used, receipt.effective_gas_price) {
+                    history.update_fee(self.hash, gas_used * gas_price);
+                }
+                if receipt.status == Some(U64::zero()) {
+                    return Ok(TransactionStatus::Failed { block_number: block_number });
+                }
+                let head = self.provider.get_block_number().await?.as_u64();
+                let confirmations = head.saturating_sub(block_number) + 1;
+                if confirmations >= self.confirmation_target {
+                    return Ok(TransactionStatus::Confirmed { block_number: block_number, confirmations: confirmations });
+                }
+                return Ok(TransactionStatus::Included { block_number: block_number, confirmations: confirmations, target: self.confirmation_target });
+            }
+        }
+
+        match self.provider.get_transaction(self.hash).await? {
+            Some(tx) => {
+                *sender_nonce = Some((tx.from, tx.nonce));
+                *unknown_polls = 0;
+            },
+            None => {
+                *unknown_polls += 1;
+                if let Some((from, nonce)) = sender_nonce {
+                    // Another transaction with the same nonce was mined.
+                    let mined_nonce = self.provider.get_transaction_count(*from, Some(BlockNumber::Latest.into())).await?;
+                    if mined_nonce > *nonce {
+                        return Ok(TransactionStatus::Dropped);
+                    }
+                }
+                if *unknown_polls >= DROPPED_AFTER_POLLS {
+                    return Ok(TransactionStatus::Dropped);
+                }
+            }
+        }
+        Ok(TransactionStatus::Pending)
+    }
+
+    fn notify(&self, observer: &Option<Weak<Mutex<StatusObserver>>>, status: &TransactionStatus) {
+        if let Some(history) = &self.history {
+            history.update_status(self.hash, status);
+        }
+        if let Some(observer) = observer.as_ref().and_then(|observer| observer.upgrade()) {
+            observer.lock().unwrap().status_changed(&self.chain_id, &self.hash.string_representation(), status);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use crate::storage::*;
+
+const KEY_FAILED_SIGN_INS_POSTFIX: &str = "_$FAILED_SIGN_INS";
+const KEY_WIPE_AFTER_POSTFIX: &str = "_$WIPE_AFTER_FAILED_SIGN_INS";
+
+/// Number of failed attempts allowed before any delay is enforced.
+const FREE_ATTEMPTS: u32 = 3;
+/// Upper bound (seconds) of the delay between attempts.
+const MAX_DELAY: f64 = 3600.0;
+/// Number of remaining attempts at which the user is warned about an upcoming wipe.
+pub const WIPE_WARNING_THRESHOLD: u32 = 3;
+
+/// Keeps track of failed sign in attempts for an account.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SignInAttempts {
+    /// Number of consecutive failed attempts.
+    pub failed: u32,
+    /// Timestamp (ms) of the last failed attempt.
+    pub last_failure: f64
+}
+
+fn attempts_key(account_name: &str) -> String {
+    format!("{}{}", account_name, KEY_FAILED_SIGN_INS_POSTFIX)
+}
+
+fn wipe_after_key(account_name: &str) -> String {
+    format!("{}{}", account_name, KEY_WIPE_AFTER_POSTFIX)
+}
+
+impl SignInAttempts {
+
+    pub fn load(storage: &dyn Storage, account_name: &str) -> Self {
+        storage.get(&attempts_key(account_name))
+            .and_then(|serialized| serde_json::from_str(&serialized).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn store(&self, storage: &mut dyn Storage, account_name: &str) {
+        let serialized = serde_json::to_string(self).expect("Unable to serialize sign in attempts");
+        storage.set(&attempts_key(account_name), &serialized);
+    }
+
+    pub fn reset(storage: &mut dyn Storage, account_name: &str) {
+        storage.delete(&attempts_key(account_name));
+    }
+
+    /// Register a failed attempt at `now` (ms).
+    pub fn register_failure(&mut self, now: f64) {
+        self.failed += 1;
+        self.last_failure = now;
+    }
+
+    /// The delay (seconds) enforced after the last failed attempt. Doubles for every attempt after `FREE_ATTEMPTS`.
+    pub fn delay(&self) -> f64 {
+        if self.failed < FREE_ATTEMPTS { return 0.0; }
+        let exponent = (self.failed - FREE_ATTEMPTS).min(31) as i32;
+        2f64.powi(exponent).min(MAX_DELAY)
+    }
+
+    /// Seconds left until a new attempt is allowed.
+    pub fn remaining_delay(&self, now: f64) -> f64 {
+        let elapsed = (now - self.last_failure) / 1000.0;
+        (self.delay() - elapsed).max(0.0)
+    }
+
+    /// Number of attempts left before the keychain is wiped, or `None` if wiping is disabled.
+    pub fn attempts_before_wipe(&self, storage: &dyn Storage, account_name: &str) -> Option<u32> {
+        let wipe_after = Self::wipe_after(storage, account_name)?;
+        Some(wipe_after.saturating_sub(self.failed))
+    }
+
+    /// The number of failed attempts after which the keychain is wiped. `None` if disabled.
+    pub fn wipe_after(storage: &dyn Storage, account_name: &str) -> Option<u32> {
+        storage.get(&wipe_after_key(account_name))
+            .and_then(|value| value.parse::<u32>().ok())
+            .filter(|value| *value > 0)
+    }
+
+    pub fn set_wipe_after(storage: &mut dyn Storage, account_name: &str, attempts: u32) {
+        if attempts == 0 {
+            storage.delete(&wipe_after_key(account_name));
+        } else {
+            storage.set(&wipe_after_key(account_name), &attempts.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(Default)]
+    struct MemoryStorage(HashMap<String, String>);
+
+    impl Storage for MemoryStorage {
+        fn get(&self, key: &str) -> Option<String> { self.0.get(key).cloned() }
+        fn set(&mut self, key: &str, value: &str) { self.0.insert(key.to_string(), value.to_string()); }
+        fn delete(&mut self, key: &str) { self.0.remove(key); }
+        fn clear(&mut self) { self.0.clear(); }
+    }
+
+    fn attempts(failed: u32) -> SignInAttempts {
+        SignInAttempts { failed: failed, last_failure: 0.0 }
+    }
+
+    #[test]
+    fn delay_doubles_after_free_attempts() {
+        let delays: Vec<f64> = (0..8).map(|failed| attempts(failed).delay()).collect();
+        assert_eq!(delays, [0.0, 0.0, 0.0, 1.0, 2.0, 4.0, 8.0, 16.0]);
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        assert_eq!(attempts(FREE_ATTEMPTS + 11).delay(), 2048.0);
+        assert_eq!(attempts(FREE_ATTEMPTS + 12).delay(), MAX_DELAY);
+        assert_eq!(attempts(u32::MAX).delay(), MAX_DELAY);
+    }
+
+    #[test]
+    fn remaining_delay_counts_down() {
+        let mut attempts = SignInAttempts::default();
+        for _ in 0..5 {
+            attempts.register_failure(10000.0);
+        }
+        assert_eq!(attempts.failed, 5);
+        assert_eq!(attempts.remaining_delay(10000.0), 4.0);
+        assert_eq!(attempts.remaining_delay(11500.0), 2.5);
+        assert_eq!(attempts.remaining_delay(20000.0), 0.0);
+    }
+
+    #[test]
+    fn stores_and_resets() {
+        let mut storage = MemoryStorage::default();
+        let mut stored = SignInAttempts::default();
+        stored.register_failure(42.0);
+        stored.store(&mut storage, "alice");
+        let loaded = SignInAttempts::load(&storage, "alice");
+        assert_eq!((loaded.failed, loaded.last_failure), (1, 42.0));
+        assert_eq!(SignInAttempts::load(&storage, "bob").failed, 0);
+
+        SignInAttempts::reset(&mut storage, "alice");
+        assert_eq!(SignInAttempts::load(&storage, "alice").failed, 0);
+    }
+
+    #[test]
+    fn counts_attempts_before_wipe() {
+        let mut storage = MemoryStorage::default();
+        assert_eq!(attempts(2).attempts_before_wipe(&storage, "alice"), None);
+
+        SignInAttempts::set_wipe_after(&mut storage, "alice", 10);
+        assert_eq!(attempts(2).attempts_before_wipe(&storage, "alice"), Some(8));
+        assert_eq!(attempts(12).attempts_before_wipe(&storage, "alice"), Some(0));
+
+        SignInAttempts::set_wipe_after(&mut storage, "alice", 0);
+        assert_eq!(SignInAttempts::wipe_after(&storage, "alice"), None);
+    }
+}
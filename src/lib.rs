@@ -7,6 +7,10 @@ use std::{panic};
 use wasm_bindgen::prelude::*;
 mod storage;
 mod utils;
+mod attempts;
+mod password;
+mod totp;
+mod metadata;
 pub mod wallet;
 pub mod session;
 //use crate::storage::*;
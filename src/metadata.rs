@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+use blockchain::chain_metadata::ChainsMetadata;
+use crate::log;
+use crate::storage::*;
+use crate::utils::now;
+
+const CHAINS_METADATA_URL: &str = "https://chainid.network/chains.json";
+const KEY_CHAINS_METADATA: &str = "$CHAINS_METADATA";
+const KEY_CHAINS_METADATA_UPDATED_AT: &str = "$CHAINS_METADATA_UPDATED_AT";
+/// Age (ms) after which the cached metadata is refreshed.
+const MAX_AGE: f64 = 7.0 * 24.0 * 3600.0 * 1000.0;
+
+/// Chains metadata shared by the wallets of a session. The downloaded list is cached in the
+/// (unencrypted, it's public data) global storage with the time it was downloaded.
+#[derive(Clone)]
+pub struct ChainsMetadataCache {
+    storage: Arc<Mutex<dyn Storage>>,
+    metadata: Arc<Mutex<Arc<ChainsMetadata>>>
+}
+
+impl ChainsMetadataCache {
+
+    pub fn new(storage: Arc<Mutex<dyn Storage>>) -> Self {
+        let metadata = Arc::new(ChainsMetadata::new(CHAINS_METADATA_URL.to_string()));
+        Self { storage: storage, metadata: Arc::new(Mutex::new(metadata)) }
+    }
+
+    /// The metadata currently in use. Empty until `load` has been called.
+    pub fn current(&self) -> Arc<ChainsMetadata> {
+        self.metadata.lock().unwrap().clone()
+    }
+
+    /// Make metadata available, in order of preference from memory, the cache, a download and
+    /// finally the snapshot bundled with the binary.
+    pub async fn load(&self) {
+        if !self.current().empty() {
+            return;
+        }
+        if let Some(metadata) = self.cached() {
+            self.replace(metadata);
+            return;
+        }
+        if let Err(error) = self.refresh().await {
+            log!("Unable to download chains metadata, using the bundled list: {}", error);
+            self.replace(ChainsMetadata::bundled(CHAINS_METADATA_URL.to_string()));
+        }
+    }
+
+    /// True if the metadata wasn't downloaded, or was downloaded too long ago.
+    pub fn is_stale(&self) -> bool {
+        match self.updated_at() {
+            Some(updated_at) => now() - updated_at > MAX_AGE,
+            None => true
+        }
+    }
+
+    /// Download the metadata, cache it and use it from now on.
+    pub async fn refresh(&self) -> blockchain::shared::Result<()> {
+        let mut metadata = ChainsMetadata::new(CHAINS_METADATA_URL.to_string());
+        metadata.download().await?;
+        log!("Downloaded metadata of {} chains.", metadata.len());
+
+        let mut storage = self.storage.lock().unwrap();
+        storage.set(KEY_CHAINS_METADATA, &metadata.serialize());
+        storage.set(KEY_CHAINS_METADATA_UPDATED_AT, &now().to_string());
+        drop(storage);
+
+        self.replace(metadata);
+        Ok(())
+    }
+
+    fn cached(&self) -> Option<ChainsMetadata> {
+        let contents = self.storage.lock().unwrap().get(KEY_CHAINS_METADATA)?;
+        let mut metadata = ChainsMetadata::new(CHAINS_METADATA_URL.to_string());
+        match metadata.parse(&contents) {
+            Ok(_) => Some(metadata),
+            Err(error) => {
+                log!("Ignoring cached chains metadata: {}", error);
+                None
+            }
+        }
+    }
+
+    fn updated_at(&self) -> Option<f64> {
+        self.storage.lock().unwrap().get(KEY_CHAINS_METADATA_UPDATED_AT)?.parse::<f64>().ok()
+    }
+
+    fn replace(&self, metadata: ChainsMetadata) {
+        *self.metadata.lock().unwrap() = Arc::new(metadata);
+    }
+}
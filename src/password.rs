@@ -0,0 +1,144 @@
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MIN_LENGTH: usize = 10;
+const DEFAULT_MIN_SCORE: u8 = 3;
+
+/// Requirements a password must fulfill before it's allowed to protect a seed phrase.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    /// Minimum zxcvbn score (0-4).
+    pub min_score: u8
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self { min_length: DEFAULT_MIN_LENGTH, min_score: DEFAULT_MIN_SCORE }
+    }
+}
+
+/// The result of a password evaluation, intended to be serialized and presented to the user.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PasswordEvaluation {
+    /// zxcvbn score, 0 (too guessable) to 4 (very unguessable).
+    pub score: u8,
+    /// Estimated number of guesses needed (log10).
+    pub guesses_log10: f64,
+    pub accepted: bool,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>
+}
+
+impl PasswordPolicy {
+
+    /// Estimate the strength of `password`. `user_inputs` (i.e. the account name) are treated as dictionary words.
+    pub fn evaluate(&self, password: &str, user_inputs: &[&str]) -> PasswordEvaluation {
+
+        let mut evaluation = match zxcvbn::zxcvbn(password, user_inputs) {
+            Ok(entropy) => {
+                let (warning, suggestions) = match entropy.feedback() {
+                    Some(feedback) => (
+                        feedback.warning().map(|w| w.to_string()),
+                        feedback.suggestions().iter().map(|s| s.to_string()).collect()),
+                    None => (None, vec![])
+                };
+                PasswordEvaluation {
+                    score: entropy.score(),
+                    guesses_log10: entropy.guesses_log10(),
+                    accepted: true,
+                    warning: warning,
+                    suggestions: suggestions }
+            },
+            Err(_) => PasswordEvaluation {
+                score: 0,
+                guesses_log10: 0.0,
+                accepted: false,
+                warning: Some("Password is empty.".to_string()),
+                suggestions: vec![] }
+        };
+
+        if password.chars().count() < self.min_length {
+            evaluation.accepted = false;
+            evaluation.suggestions.insert(0, format!("Use at least {} characters.", self.min_length));
+        }
+        if evaluation.score < self.min_score {
+            evaluation.accepted = false;
+            if evaluation.warning.is_none() {
+                evaluation.warning = Some(format!("Password is too weak (score {} of required {}).", evaluation.score, self.min_score));
+            }
+        }
+        evaluation
+    }
+}
+
+impl PasswordEvaluation {
+
+    /// A human readable summary of the warning and suggestions.
+    pub fn message(&self) -> String {
+        let mut parts: Vec<String> = vec![];
+        if let Some(warning) = &self.warning { parts.push(warning.clone()); }
+        parts.extend(self.suggestions.iter().cloned());
+        parts.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRONG: &str = "r7#Kq!vZ2m@Lp9$wT";
+
+    #[test]
+    fn accepts_long_unguessable_passwords() {
+        let evaluation = PasswordPolicy::default().evaluate(STRONG, &[]);
+        assert!(evaluation.accepted, "{}", evaluation.message());
+        assert!(evaluation.score >= DEFAULT_MIN_SCORE);
+    }
+
+    #[test]
+    fn rejects_empty_passwords() {
+        let evaluation = PasswordPolicy::default().evaluate("", &[]);
+        assert!(!evaluation.accepted);
+        assert_eq!(evaluation.warning.as_deref(), Some("Password is empty."));
+    }
+
+    #[test]
+    fn rejects_short_passwords() {
+        let short: String = STRONG.chars().take(DEFAULT_MIN_LENGTH - 1).collect();
+        let evaluation = PasswordPolicy::default().evaluate(&short, &[]);
+        assert!(!evaluation.accepted);
+        assert_eq!(evaluation.suggestions[0], format!("Use at least {} characters.", DEFAULT_MIN_LENGTH));
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        let policy = PasswordPolicy { min_length: 4, min_score: 0 };
+        assert!(policy.evaluate("äöüå", &[]).accepted);
+        assert!(!policy.evaluate("äöü", &[]).accepted);
+    }
+
+    #[test]
+    fn rejects_guessable_passwords() {
+        let evaluation = PasswordPolicy::default().evaluate("passwordpassword", &[]);
+        assert!(!evaluation.accepted);
+        assert!(evaluation.score < DEFAULT_MIN_SCORE);
+        assert!(evaluation.warning.is_some());
+    }
+
+    #[test]
+    fn treats_user_inputs_as_guessable() {
+        let account_name = "Zq8vLr2xWm4t";
+        let policy = PasswordPolicy { min_length: 1, min_score: 4 };
+        assert!(!policy.evaluate(account_name, &[account_name]).accepted);
+    }
+
+    #[test]
+    fn message_joins_warning_and_suggestions() {
+        let evaluation = PasswordEvaluation { score: 1,
+                                              guesses_log10: 2.0,
+                                              accepted: false,
+                                              warning: Some("Too weak.".to_string()),
+                                              suggestions: vec!["Add a word.".to_string(), "Avoid dates.".to_string()] };
+        assert_eq!(evaluation.message(), "Too weak. Add a word. Avoid dates.");
+    }
+}
@@ -1,19 +1,31 @@
 extern crate web_sys;
 
 use std::borrow::BorrowMut;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
-use std::{panic, sync::Arc, sync::Mutex};
+use std::{panic, sync::Arc, sync::Mutex, sync::Weak};
 use wasm_bindgen::prelude::*;
 
+use crate::attempts::*;
+use crate::password::*;
+use crate::totp::*;
+use crate::metadata::ChainsMetadataCache;
 use crate::storage::*;
 use crate::utils::*;
 use crate::wallet::*;
 use crate::log;
 use blockchain::account::*;
 use blockchain::mnemonic;
+use blockchain::amount::*;
 use blockchain::chain::*;
+use blockchain::chain_metadata::{ChainInfo, NetworkOverrides};
+use blockchain::explorer::ExplorerApi;
+use blockchain::history::TransactionRecord;
+use blockchain::rpc::diagnose;
+use blockchain::scanner::*;
+use blockchain::transaction::*;
 
 #[derive(Debug, Clone)]
 pub enum ValidationError {
@@ -59,10 +71,20 @@ impl Wazzaaap {
         self.observer.lock().unwrap().chains_serialized()
     }
 
-    pub fn balance_for(&self, id: &str) -> f64 {
+    /** Returns a serialized list of `DisabledChainDescriptor`. */
+    pub fn disabled_chains_serialized(&self) -> String {
+        self.observer.lock().unwrap().disabled_chains_serialized()
+    }
+
+    pub fn balance_for(&self, id: &str) -> String {
         self.observer.lock().unwrap().balance_for(id)
     }
 
+    /** Separators and precision used for balances and entered amounts. An empty `grouping_separator` disables grouping. */
+    pub fn set_amount_format(&self, decimal_separator: char, grouping_separator: &str, max_fraction_digits: Option<u32>) {
+        self.observer.lock().unwrap().set_amount_format(decimal_separator, grouping_separator, max_fraction_digits)
+    }
+
     pub fn available_accounts(&self) -> String {
         self.observer.lock().unwrap().available_accounts()
     }
@@ -70,24 +92,32 @@ impl Wazzaaap {
     pub fn account_name(&self) -> String { 
         self.observer.lock().unwrap().account_name()
      }
+
+    pub fn transaction_status(&self, hash: &str) -> String {
+        self.observer.lock().unwrap().transaction_status(hash)
+    }
 }
 
 #[wasm_bindgen]
 pub struct WalletObserver {
     address: Option<String>,
     chains: Vec<ChainDescriptor>,
+    disabled_chains: Vec<DisabledChainDescriptor>,
     available_accounts: String,
     active_account_name: String,
 
-    /** <id, (gwei, denomiator)> */
-    balances: HashMap<String, (f64, f64)>
+    /** <id, (balance in base units, decimals)> */
+    balances: HashMap<String, (U256, u32)>,
+    amount_format: AmountFormat,
+    /** Last known status of transactions sent in this session, by hash. */
+    transactions: HashMap<String, TransactionStatusDescriptor>
 } 
 
 #[wasm_bindgen]
 impl WalletObserver {
 
     fn new() -> Self {
-        Self { address: None, chains: vec![], available_accounts: String::new(), active_account_name: String::new(), balances: HashMap::new() }
+        Self { address: None, chains: vec![], disabled_chains: vec![], available_accounts: String::new(), active_account_name: String::new(), balances: HashMap::new(), amount_format: AmountFormat::default(), transactions: HashMap::new() }
     }
 
     fn available_accounts(&self) -> String { self.available_accounts.clone() }
@@ -107,37 +137,96 @@ impl WalletObserver {
         return serde_json::to_string(&self.chains).expect("Unable to serialize chains"); 
     }
 
+    pub fn disabled_chains_serialized(&self) -> String {
+        serde_json::to_string(&self.disabled_chains).expect("Unable to serialize disabled chains")
+    }
+
     #[wasm_bindgen]
-    pub fn balance_for(&self, id: &str) -> f64 {
+    pub fn balance_for(&self, id: &str) -> String {
 
-        if let Some(gwei_denomiator) = self.balances.get(&id.to_string()) {
-            return gwei_denomiator.0 / gwei_denomiator.1;
+        if let Some((balance, decimals)) = self.balances.get(&id.to_string()) {
+            return self.amount_format.format(*balance, *decimals);
         }
         panic!("No balance entry for: {}", id);
     }
 
-    pub fn denomiator_for(&self, id: &str) -> f64 {
-        if let Some(gwei_denomiator) = self.balances.get(&id.to_string()) {
-            return gwei_denomiator.1;
+    pub fn decimals_for(&self, id: &str) -> u32 {
+        if let Some((_, decimals)) = self.balances.get(&id.to_string()) {
+            return *decimals;
         }
         panic!("No balance entry for: {}", id);
     }
 
+    pub fn set_amount_format(&mut self, decimal_separator: char, grouping_separator: &str, max_fraction_digits: Option<u32>) {
+        self.amount_format = AmountFormat {
+            decimal_separator: decimal_separator,
+            grouping_separator: grouping_separator.chars().next(),
+            max_fraction_digits: max_fraction_digits,
+            rounding: Rounding::Down };
+    }
+
+    /** Parse an amount of chain (id) or token (address) entered by the user into base units. */
+    fn parse_amount(&self, id: &str, amount: &str) -> Result<U256, AmountError> {
+        self.amount_format.parse(amount, self.decimals_for(id))
+    }
+
+    /** Serialized `TransactionStatusDescriptor` of a transaction sent in this session, empty if unknown. */
+    pub fn transaction_status(&self, hash: &str) -> String {
+        match self.transactions.get(hash) {
+            Some(status) => serde_json::to_string(status).expect("Unable to serialize transaction status"),
+            None => "".to_string()
+        }
+    }
+
     fn set_address(&mut self, address: String) {
         self.address = Some(address);
     }
 
-    fn set_chains(&mut self, chains: Vec<ChainDescriptor>) {
+    /** Replace the chains, forgetting the balances of removed chains and tokens. */
+    fn set_chains(&mut self, chains: Vec<ChainDescriptor>, disabled_chains: Vec<DisabledChainDescriptor>) {
+        self.balances.retain(|id, _| chains.iter().any(|chain| {
+            chain.id == *id || chain.tokens.iter().any(|token| token.contract_address == *id)
+        }));
         self.chains = chains;
+        self.disabled_chains = disabled_chains;
     }
 
     fn set_available_accounts(&mut self, available_accounts: String) { self.available_accounts = available_accounts; }
 }
 
+/** Run a JS callback once the current task yields. Observers are notified while their mutex is locked, and the JS handlers
+ read the observer again, which would lock it recursively. */
+fn defer<F>(callback: F) where F: FnOnce() + 'static {
+    wasm_bindgen_futures::spawn_local(async move { callback() });
+}
+
 impl BalanceUpdatedObserver for WalletObserver {
 
-    fn balance_updated(&mut self, gwei: u128, token: &dyn TokenData) {
-        self.balances.insert(token.identifier(), (gwei as f64, token.denomiator() as f64));
+    fn balance_updated(&mut self, balance: U256, token: &dyn TokenData) {
+        let previous = self.balances.insert(token.identifier(), (balance, token.decimals()));
+        if previous.map_or(false, |(previous, _)| previous != balance) {
+            let id = token.identifier();
+            defer(move || balanceChanged(&id));
+        }
+    }
+}
+
+impl TransactionStatusObserver for WalletObserver {
+
+    fn status_changed(&mut self, chain_id: &str, hash: &str, status: &TransactionStatus) {
+        let descriptor = TransactionStatusDescriptor::new(chain_id, hash, status);
+        let serialized = serde_json::to_string(&descriptor).expect("Unable to serialize transaction status");
+        self.transactions.insert(hash.to_string(), descriptor);
+        defer(move || transactionStatusChanged(&serialized));
+    }
+}
+
+impl IncomingTransferObserver for WalletObserver {
+
+    fn transfer_received(&mut self, record: &TransactionRecord) {
+        let descriptor = TransactionRecordDescriptor::new(record, &self.chains);
+        let serialized = serde_json::to_string(&descriptor).expect("Unable to serialize transaction record");
+        defer(move || transferReceived(&serialized));
     }
 }
 
@@ -145,6 +234,16 @@ impl BalanceUpdatedObserver for WalletObserver {
 extern {
     pub fn stateChanged(state: SessionState);
     pub fn walletInitialized();
+    /** `attempts_before_wipe` is -1 if the keychain will never be wiped. */
+    pub fn signInFailed(failed_attempts: u32, remaining_delay: f64, attempts_before_wipe: i32);
+    /** Called with a serialized `TransactionStatusDescriptor` whenever a sent transaction changes status. */
+    pub fn transactionStatusChanged(status: &str);
+    /** Called with a serialized `TransactionRecordDescriptor` for every transfer to the account found by the block scanner. */
+    pub fn transferReceived(record: &str);
+    /** Called with the chain id or token address when a known balance changes. */
+    pub fn balanceChanged(id: &str);
+    /** Called when a chain fails to load and is kept offline, and with an empty `error` once it's connected again. */
+    pub fn chainStatusChanged(chain_id: &str, error: &str);
 }
 
 #[wasm_bindgen]
@@ -153,25 +252,55 @@ extern {
 pub enum SessionState {
     New = 0,
     Unauthenticated = 1,
-    Authenticated = 2
+    Authenticated = 2,
+    /** Signed in, but the keys have been removed. Requires `unlock` to sign again. */
+    Locked = 3,
+    /** The password was accepted, but a one time password is required to complete the sign in. */
+    AwaitingSecondFactor = 4
 }
 
-type Authentication = KeyChain<DefaultStorage>;
+type Authentication = KeyChain<dyn Storage>;
+
+/** The keychain of an authenticated profile. `sealed` is set if it's the duress (decoy) profile. */
+#[derive(Clone)]
+struct Profile {
+    keychain: Arc<Mutex<Authentication>>,
+    sealed: Option<Arc<Mutex<SealedStorage>>>
+}
 
 #[wasm_bindgen]
 pub struct Session {
     account_name: String,
-    state: SessionState,
+    state: Cell<SessionState>,
     storage: Arc<Mutex<DefaultStorage>>,
-    keychain: Option<Arc<Mutex<Authentication>>>,
+    keychain: RefCell<Option<Arc<Mutex<Authentication>>>>,
+    /** Storage of the duress profile, if that's the one signed in. */
+    sealed: Option<Arc<Mutex<SealedStorage>>>,
+    /** Profile of an account waiting for its second factor to be verified. */
+    pending_profile: Option<Profile>,
+    /** Second factor being enrolled, but not yet confirmed. */
+    pending_second_factor: RefCell<Option<SecondFactor>>,
     wallet: Option<Arc<Mutex<Wallet<Authentication>>>>,
     wallet_observer: Arc<Mutex<WalletObserver>>,
-    base_url: String
+    chains_metadata: ChainsMetadataCache,
+    base_url: String,
+
+    /** Seconds of inactivity before the session locks itself. 0 means never. */
+    auto_lock_timeout: u32,
+    /** Timestamp (ms) of the last user activity. */
+    last_activity: Cell<f64>,
+    password_policy: PasswordPolicy
 }
 
 const KEY_CURRENTLY_ACTIVE_ACCOUNT_NAME: &str = "KEY_CURRENTLY_ACTIVE_ACCOUNT_NAME";
 const KEY_AVAILABLE_ACCOUNT_NAMES: &str = "$KEY_AVAILABLE_ACCOUNT_NAMES";
+const KEY_AUTO_LOCK_TIMEOUT: &str = "$KEY_AUTO_LOCK_TIMEOUT";
+const DEFAULT_AUTO_LOCK_TIMEOUT: u32 = 300;
 const DELIMITER: &str = ";";
+const MAX_CHAIN_CANDIDATES: usize = 20;
+/** Values of `vault_state_key`, equally long so that the encrypted values are too. */
+const VAULT_STATE_FILLER: &str = "filler";
+const VAULT_STATE_SEALED: &str = "sealed";
 
 #[wasm_bindgen]
 impl Session {
@@ -195,17 +324,31 @@ impl Session {
         }
         wazzaaap.observer.lock().unwrap().set_account_name(&account_name);
 
+        let auto_lock_timeout = storage.get(KEY_AUTO_LOCK_TIMEOUT)
+            .and_then(|timeout| timeout.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_AUTO_LOCK_TIMEOUT);
+
+        let storage = Arc::new(Mutex::new(storage));
+        let chains_metadata = ChainsMetadataCache::new(storage.clone());
+
         Self { account_name: account_name,
-               state: state,
-               storage: Arc::new(Mutex::new(storage)), 
-               keychain: None,
+               state: Cell::new(state),
+               storage: storage, 
+               keychain: RefCell::new(None),
+               sealed: None,
+               pending_profile: None,
+               pending_second_factor: RefCell::new(None),
                wallet: None,
                wallet_observer: wazzaaap.observer().clone(),
-               base_url: base_url.to_string() }
+               chains_metadata: chains_metadata,
+               base_url: base_url.to_string(),
+               auto_lock_timeout: auto_lock_timeout,
+               last_activity: Cell::new(now()),
+               password_policy: PasswordPolicy::default() }
     }
 
     pub fn state(&self) -> SessionState {
-        self.state
+        self.state.get()
     }
 
     pub fn account_name(&self) -> String {
@@ -218,8 +361,8 @@ impl Session {
         let mut unlocked = self.storage.lock().unwrap();
         let storage: &mut std::sync::MutexGuard<DefaultStorage> = unlocked.borrow_mut();
         storage.clear();
-        self.state = SessionState::New;
-        stateChanged(self.state);
+        self.state.set(SessionState::New);
+        stateChanged(self.state.get());
     }
 
     pub fn random_mnemonic(&self) -> String {
@@ -228,31 +371,149 @@ impl Session {
 
     pub async fn sign_in(&mut self, account_name: &str, password: &str) -> bool {
 
-        assert!(matches!(self.state, SessionState::Unauthenticated));
+        assert!(matches!(self.state.get(), SessionState::Unauthenticated));
         
         let mut signin_ok: bool = false;
 
-        {
-            let storage_guard = self.storage.lock();
-            let storage = storage_guard.as_deref().unwrap();
-            
-            if Authentication::check_password(storage, account_name, password) {
-                let keychain = Arc::new(Mutex::new(Authentication::new(self.storage.clone(), password)));
-                self.keychain = Some(keychain.clone());
-                signin_ok = true;
-            } else {
-                errorCallback("Invalid credentials");
+        if let Some(profile) = self.authenticate(account_name, password) {
+
+            if profile.keychain.lock().unwrap().get(&secret_key(account_name)).is_some() {
+                self.account_name = account_name.to_string();
+                self.pending_profile = Some(profile);
+                self.state.set(SessionState::AwaitingSecondFactor);
+                stateChanged(self.state.get());
+                return true;
             }
+
+            self.reset_failed_attempts(account_name);
+            self.set_profile(profile);
+            signin_ok = true;
         }
         if signin_ok {
-            self.sign_in_complete(account_name, None).await.expect("Argh! Sign in (log in) completion failed.");
+            return self.complete_sign_in(account_name, None).await;
         }
         signin_ok
     }
 
+    /** Complete a sign in using a one time password or an unused recovery code. */
+    pub async fn verify_second_factor(&mut self, code: &str) -> bool {
+
+        assert!(matches!(self.state.get(), SessionState::AwaitingSecondFactor));
+
+        let account_name = self.account_name.clone();
+        if self.is_throttled(&account_name) { return false; }
+
+        let profile = self.pending_profile.clone().expect("No pending profile!");
+        let verified = {
+            let mut keychain = profile.keychain.lock().unwrap();
+            Self::verify_one_time_password(&mut *keychain, &account_name, code) || Self::consume_recovery_code(&mut *keychain, &account_name, code)
+        };
+
+        if !verified {
+            self.register_failed_attempt(&account_name);
+            return false;
+        }
+
+        self.reset_failed_attempts(&account_name);
+        self.pending_profile = None;
+        self.set_profile(profile);
+        self.complete_sign_in(&account_name, None).await
+    }
+
+    pub fn second_factor_enabled(&self) -> bool {
+        match &*self.keychain.borrow() {
+            Some(keychain) => keychain.lock().unwrap().get(&secret_key(&self.account_name)).is_some(),
+            None => false
+        }
+    }
+
+    /** Generate a new TOTP secret for the signed in account and return its otpauth:// URI. 
+        The secret is not used until confirmed by `confirm_second_factor`. */
+    pub fn enroll_second_factor(&mut self) -> String {
+        if self.state.get() != SessionState::Authenticated {
+            errorCallback("Must be signed in to enable two factor authentication.");
+            return String::new();
+        }
+        self.touch();
+
+        match SecondFactor::generate(&self.account_name) {
+            Ok(second_factor) => {
+                let url = second_factor.url();
+                *self.pending_second_factor.get_mut() = Some(second_factor);
+                url
+            },
+            Err(error) => {
+                errorCallback(&format!("Unable to create TOTP secret: {}", error));
+                String::new()
+            }
+        }
+    }
+
+    /** Verify `code` against the enrolled secret and store it in the keychain. 
+        Returns the serialized list of recovery codes, or an empty string on failure. 
+        The duress profile keeps its copy of the previous second factor until `set_duress_password` is called again. */
+    pub fn confirm_second_factor(&mut self, code: &str) -> String {
+        self.touch();
+        let (second_factor, keychain) = match (self.pending_second_factor.get_mut(), self.keychain.get_mut()) {
+            (Some(second_factor), Some(keychain)) => (second_factor, keychain.clone()),
+            _ => {
+                errorCallback("No two factor enrollment in progress.");
+                return String::new();
+            }
+        };
+
+        let step = match second_factor.verify(code, now(), None) {
+            Some(step) => step,
+            None => {
+                errorCallback("Invalid code");
+                return String::new();
+            }
+        };
+
+        let recovery_codes = generate_recovery_codes();
+        let recovery_codes_serialized = serde_json::to_string(&recovery_codes).expect("Unable to serialize recovery codes");
+        {
+            let mut keychain = keychain.lock().unwrap();
+            keychain.set(&secret_key(&self.account_name), &second_factor.secret());
+            keychain.set(&recovery_codes_key(&self.account_name), &recovery_codes_serialized);
+            keychain.set(&last_step_key(&self.account_name), &step.to_string());
+        }
+        *self.pending_second_factor.get_mut() = None;
+        log!("Two factor authentication enabled for {}", self.account_name);
+        recovery_codes_serialized
+    }
+
+    /** Remove the second factor from the signed in account. Requires a valid one time password or recovery code. 
+        The duress profile keeps its copy until `set_duress_password` is called again. */
+    pub fn disable_second_factor(&mut self, code: &str) -> bool {
+        self.touch();
+        let account_name = self.account_name.clone();
+        let keychain = match (self.keychain.get_mut(), self.state.get()) {
+            (Some(keychain), SessionState::Authenticated) => keychain.clone(),
+            _ => {
+                errorCallback("Must be signed in to disable two factor authentication.");
+                return false;
+            }
+        };
+        let mut keychain = keychain.lock().unwrap();
+        if keychain.get(&secret_key(&account_name)).is_none() {
+            errorCallback("Two factor authentication is not enabled.");
+            return false;
+        }
+        let verified = Self::verify_one_time_password(&mut *keychain, &account_name, code) || Self::consume_recovery_code(&mut *keychain, &account_name, code);
+        if !verified {
+            errorCallback("Invalid code");
+            return false;
+        }
+        keychain.delete(&secret_key(&account_name));
+        keychain.delete(&recovery_codes_key(&account_name));
+        keychain.delete(&last_step_key(&account_name));
+        true
+    }
+
     pub async fn create_user(&mut self, account_name: &str, password: &str, mnemonic: &str) -> bool {
 
-        assert!(matches!(self.state, SessionState::New));
+        assert!(matches!(self.state.get(), SessionState::New));
         
         if let Err(e) = self.validate(account_name, password, mnemonic) {
             errorCallback(&format!("Error: {}", e));
@@ -262,39 +523,70 @@ impl Session {
         let mut keychain = Authentication::new(self.storage.clone(), password);
         keychain.set(account_name, password);
         let keychain = Arc::new(Mutex::new(keychain));
-        self.keychain = Some(keychain.clone());
+        self.set_profile(Profile { keychain: keychain, sealed: None });
         
-        self.sign_in_complete(account_name, Some(mnemonic)).await.expect("Argh! Sign in completion failed");
-        
-        true
+        self.complete_sign_in(account_name, Some(mnemonic)).await
+    }
+
+    /** `sign_in_complete`, reporting a failure through `errorCallback`. Chains that can't be connected don't fail the sign in. */
+    async fn complete_sign_in(&mut self, account_name: &str, mnemonic: Option<&str>) -> bool {
+        match self.sign_in_complete(account_name, mnemonic).await {
+            Ok(()) => true,
+            Err(error) => {
+                errorCallback(&format!("Unable to complete sign in: {}", error));
+                false
+            }
+        }
     }
 
     async fn sign_in_complete(&mut self, account_name: &str, mnemonic: Option<&str>) -> blockchain::shared::Result<()> {
 
         self.account_name = account_name.to_string();
+        let failures: Vec<ChainFailure>;
+
+        // A duress profile reseals its vault on every sign in, a filler is rewritten instead.
+        match &self.sealed {
+            Some(sealed) => sealed.lock().unwrap().reseal(),
+            None => {
+                let keychain = self.keychain.get_mut().clone().expect("No keychain!");
+                let mut keychain = keychain.lock().unwrap();
+                let missing = self.storage.lock().unwrap().get(&vault_key(account_name)).is_none();
+                if missing {
+                    keychain.set(&vault_state_key(account_name), VAULT_STATE_FILLER);
+                }
+                if keychain.get(&vault_state_key(account_name)).as_deref() == Some(VAULT_STATE_FILLER) {
+                    SealedStorage::write_filler(&mut *self.storage.lock().unwrap(), &vault_key(account_name));
+                }
+            }
+        }
 
         self.create_wallet(account_name, mnemonic);
         
         if let Some(wallet_mutex) = &self.wallet {
            
-           let mut wallet = wallet_mutex.lock().unwrap();
-
-           wallet.initialize().await?;
+           failures = Wallet::initialize(wallet_mutex).await?;
 
+           let wallet = wallet_mutex.lock().unwrap();
            let mut observer = self.wallet_observer.lock().unwrap();
            observer.set_address(wallet.address());
            log!("denna adressen gäller: {}", wallet.address());
-           observer.set_chains(wallet.chains());
+           observer.set_chains(wallet.chains(), wallet.disabled_chains());
 
         } else {
             panic!("No wallet!");
         }
 
+        self.subscribe_balances();
+        self.refresh_chains_metadata();
         self.store_account_name(account_name);
-        self.state = SessionState::Authenticated;
+        self.state.set(SessionState::Authenticated);
+        self.touch();
         
-        stateChanged(self.state);
+        stateChanged(self.state.get());
         walletInitialized();
+        for failure in failures.iter() {
+            chainStatusChanged(&failure.chain_id, &failure.error);
+        }
         Ok(())
         
     }
@@ -302,20 +594,288 @@ impl Session {
     pub fn sign_out(&mut self) {
 
         self.storage.lock().unwrap().delete(KEY_CURRENTLY_ACTIVE_ACCOUNT_NAME);
-        self.state = SessionState::New;
+        if let Some(wallet_arc) = &self.wallet {
+            for chain in wallet_arc.lock().unwrap().portfolio_chains() {
+                chain.unsubscribe_balances();
+            }
+        }
+        self.state.set(SessionState::New);
         self.account_name = "".to_string();
         self.wallet = None;
-        self.keychain = None;
+        *self.keychain.get_mut() = None;
+        self.sealed = None;
+        self.pending_profile = None;
+        *self.pending_second_factor.get_mut() = None;
+
+        stateChanged(self.state.get());
+    }
+
+    /** Remove the keychain and all private keys, but keep the portfolio so that it can be displayed while locked. 
+        Takes `&self` so that it can be called while an asynchronous call, i.e. a transfer, is pending. */
+    pub fn lock(&self) {
+
+        if self.state.get() != SessionState::Authenticated { return; }
+
+        if let Some(wallet) = &self.wallet {
+            wallet.lock().unwrap().lock();
+        }
+        self.keychain.replace(None);
+        self.pending_second_factor.replace(None);
+        self.state.set(SessionState::Locked);
+        log!("Session locked.");
+
+        stateChanged(self.state.get());
+    }
+
+    /** Restore the keys of a locked session using only the password. */
+    pub fn unlock(&mut self, password: &str) -> bool {
+
+        if self.state.get() != SessionState::Locked {
+            errorCallback("Session is not locked.");
+            return false;
+        }
+
+        let account_name = self.account_name.clone();
+        let profile = match self.authenticate(&account_name, password) {
+            Some(profile) => profile,
+            None => return false
+        };
+        // Only the profile that was locked can be unlocked. The wallet keeps using the already opened sealed storage.
+        let keychain = match (&self.sealed, profile.sealed) {
+            (None, None) => profile.keychain,
+            (Some(sealed), Some(_)) => Arc::new(Mutex::new(Authentication::new(sealed.clone(), password))),
+            _ => {
+                errorCallback("Invalid credentials");
+                return false;
+            }
+        };
+        self.reset_failed_attempts(&account_name);
+
+        if let Some(wallet) = &self.wallet {
+            if let Err(error) = wallet.lock().unwrap().unlock(keychain.clone()) {
+                errorCallback(&format!("Unable to unlock: {}", error));
+                return false;
+            }
+        }
+        *self.keychain.get_mut() = Some(keychain);
+        self.state.set(SessionState::Authenticated);
+        self.touch();
+
+        stateChanged(self.state.get());
+        true
+    }
+
+    /** Register user activity, postponing the auto lock. */
+    pub fn touch(&self) {
+        self.last_activity.set(now());
+    }
+
+    /** Lock the session if it has been idle for longer than the auto lock timeout. Intended to be called periodically. */
+    pub fn check_idle(&self) -> SessionState {
+        if self.state.get() == SessionState::Authenticated && self.auto_lock_timeout > 0 {
+            let idle_seconds = (now() - self.last_activity.get()) / 1000.0;
+            if idle_seconds >= self.auto_lock_timeout as f64 {
+                self.lock();
+            }
+        }
+        self.state.get()
+    }
+
+    pub fn auto_lock_timeout(&self) -> u32 {
+        self.auto_lock_timeout
+    }
 
-        stateChanged(self.state);
+    /** Set the number of idle seconds before the session locks itself. 0 disables auto lock. */
+    pub fn set_auto_lock_timeout(&mut self, seconds: u32) {
+        self.auto_lock_timeout = seconds;
+        self.storage.lock().unwrap().set(KEY_AUTO_LOCK_TIMEOUT, &seconds.to_string());
+        self.touch();
+    }
+
+    /** Set the requirements for new passwords. `min_score` is a zxcvbn score (0-4). */
+    pub fn set_password_policy(&mut self, min_length: usize, min_score: u8) {
+        self.password_policy = PasswordPolicy { min_length: min_length, min_score: min_score.min(4) };
+    }
+
+    /** Returns a serialized `PasswordEvaluation` containing score and feedback for `password`. */
+    pub fn evaluate_password(&self, account_name: &str, password: &str) -> String {
+        let evaluation = self.password_policy.evaluate(password, &[account_name]);
+        serde_json::to_string(&evaluation).expect("Unable to serialize password evaluation")
+    }
+
+    /** Re-encrypt the keychain of the signed in account using `new_password`. */
+    pub fn change_password(&mut self, old_password: &str, new_password: &str) -> bool {
+
+        if self.state.get() != SessionState::Authenticated {
+            errorCallback("Must be signed in to change password.");
+            return false;
+        }
+        self.touch();
+
+        let account_name = self.account_name.clone();
+        let evaluation = self.password_policy.evaluate(new_password, &[&account_name]);
+        if !evaluation.accepted {
+            errorCallback(&format!("Error: {}", ValidationError::InvalidPassword(evaluation.message())));
+            return false;
+        }
+
+        let old_keychain = match self.authenticate(&account_name, old_password) {
+            Some(profile) if profile.sealed.is_some() == self.sealed.is_some() => profile.keychain,
+            Some(_) => {
+                errorCallback("Invalid credentials");
+                return false;
+            },
+            None => return false
+        };
+        self.reset_failed_attempts(&account_name);
+
+        if self.password_in_use(&account_name, new_password) {
+            errorCallback(&format!("Error: {}", ValidationError::InvalidPassword("Choose another password.".to_string())));
+            return false;
+        }
+
+        let account_identifier = AccountIdentifier { id: account_name.clone() };
+        let seed_phrase = match old_keychain.lock().unwrap().get_seed_phrase(&account_identifier) {
+            Some(seed_phrase) => seed_phrase,
+            None => {
+                errorCallback("No seed phrase found for account.");
+                return false;
+            }
+        };
+
+        if let Some(sealed) = &self.sealed {
+            sealed.lock().unwrap().rekey(new_password);
+        }
+        let mut keychain = Authentication::new(self.profile_storage(), new_password);
+        keychain.save_seed_phrase(&account_identifier, &seed_phrase);
+        for key in [secret_key(&account_name), recovery_codes_key(&account_name), last_step_key(&account_name), vault_state_key(&account_name)] {
+            if let Some(value) = old_keychain.lock().unwrap().get(&key) {
+                keychain.set(&key, &value);
+            }
+        }
+        keychain.set(&account_name, new_password);
+        let keychain = Arc::new(Mutex::new(keychain));
+
+        if let Some(wallet) = &self.wallet {
+            let mut wallet = wallet.lock().unwrap();
+            wallet.lock();
+            if let Err(error) = wallet.unlock(keychain.clone()) {
+                errorCallback(&format!("Unable to reload wallet: {}", error));
+                return false;
+            }
+        }
+        *self.keychain.get_mut() = Some(keychain);
+        log!("Password changed for {}", account_name);
+        true
+    }
+
+    /** Create (or replace) the duress profile of the signed in account. Signing in using `duress_password` 
+        opens a decoy wallet based on `mnemonic` with its own chains and tokens. The second factor of the account is
+        copied, so that both profiles sign in the same way. Set it again after enabling or disabling the second factor. */
+    pub fn set_duress_password(&mut self, password: &str, duress_password: &str, mnemonic: &str) -> bool {
+
+        if self.state.get() != SessionState::Authenticated || self.sealed.is_some() {
+            errorCallback("Must be signed in to change this setting.");
+            return false;
+        }
+        self.touch();
+
+        let account_name = self.account_name.clone();
+        match self.authenticate(&account_name, password) {
+            Some(profile) if profile.sealed.is_none() => self.reset_failed_attempts(&account_name),
+            Some(_) => {
+                errorCallback("Invalid credentials");
+                return false;
+            },
+            None => return false
+        }
+
+        let evaluation = self.password_policy.evaluate(duress_password, &[&account_name]);
+        if !evaluation.accepted {
+            errorCallback(&format!("Error: {}", ValidationError::InvalidPassword(evaluation.message())));
+            return false;
+        }
+        if Authentication::check_password(&*self.storage.lock().unwrap(), &account_name, duress_password) {
+            errorCallback(&format!("Error: {}", ValidationError::InvalidPassword("Must differ from the account password.".to_string())));
+            return false;
+        }
+        if mnemonic::validate(mnemonic) == false {
+            errorCallback(&format!("Error: {}", ValidationError::InvalidMnemonic));
+            return false;
+        }
+
+        let sealed = Arc::new(Mutex::new(SealedStorage::create(self.storage.clone(), &vault_key(&account_name), duress_password)));
+        let mut keychain = Authentication::new(sealed, duress_password);
+        keychain.set(&account_name, duress_password);
+        keychain.save_seed_phrase(&AccountIdentifier { id: account_name.clone() }, mnemonic);
+        if let Some(account_keychain) = self.keychain.get_mut() {
+            let mut account_keychain = account_keychain.lock().unwrap();
+            for key in [secret_key(&account_name), recovery_codes_key(&account_name), last_step_key(&account_name)] {
+                if let Some(value) = account_keychain.get(&key) {
+                    keychain.set(&key, &value);
+                }
+            }
+            account_keychain.set(&vault_state_key(&account_name), VAULT_STATE_SEALED);
+        }
+        true
+    }
+
+    /** Remove the duress profile of the signed in account. */
+    pub fn remove_duress_password(&mut self, password: &str) -> bool {
+
+        if self.state.get() != SessionState::Authenticated || self.sealed.is_some() {
+            errorCallback("Must be signed in to change this setting.");
+            return false;
+        }
+        self.touch();
+
+        let account_name = self.account_name.clone();
+        match self.authenticate(&account_name, password) {
+            Some(profile) if profile.sealed.is_none() => self.reset_failed_attempts(&account_name),
+            Some(_) => {
+                errorCallback("Invalid credentials");
+                return false;
+            },
+            None => return false
+        }
+        if let Some(keychain) = self.keychain.get_mut() {
+            keychain.lock().unwrap().set(&vault_state_key(&account_name), VAULT_STATE_FILLER);
+        }
+        SealedStorage::write_filler(&mut *self.storage.lock().unwrap(), &vault_key(&account_name));
+        true
+    }
+
+    pub fn failed_sign_in_attempts(&self, account_name: &str) -> u32 {
+        SignInAttempts::load(&*self.storage.lock().unwrap(), account_name).failed
+    }
+
+    /** Seconds left until `account_name` is allowed another sign in attempt. */
+    pub fn sign_in_delay(&self, account_name: &str) -> f64 {
+        SignInAttempts::load(&*self.storage.lock().unwrap(), account_name).remaining_delay(now())
+    }
+
+    /** Number of failed attempts after which the keychain of the current account is wiped. 0 if disabled. */
+    pub fn wipe_after_failed_attempts(&self) -> u32 {
+        SignInAttempts::wipe_after(&*self.storage.lock().unwrap(), &self.account_name).unwrap_or(0)
+    }
+
+    /** Wipe the keychain of the current account after `attempts` failed sign in attempts. 0 disables. */
+    pub fn set_wipe_after_failed_attempts(&mut self, attempts: u32) -> bool {
+        if self.state.get() != SessionState::Authenticated {
+            errorCallback("Must be signed in to change this setting.");
+            return false;
+        }
+        SignInAttempts::set_wipe_after(&mut *self.storage.lock().unwrap(), &self.account_name, attempts);
+        self.touch();
+        true
     }
 
     pub fn prepare_sign_in(&mut self, account_name: &str) {
         self.storage.lock().unwrap().set(KEY_CURRENTLY_ACTIVE_ACCOUNT_NAME, account_name);
         self.account_name = account_name.to_string();
-        self.state = SessionState::Unauthenticated;
+        self.state.set(SessionState::Unauthenticated);
         self.wallet_observer.lock().unwrap().set_account_name(&account_name);
-        stateChanged(self.state);
+        stateChanged(self.state.get());
     }
 
     fn available_accounts(&self) -> String {
@@ -334,7 +894,8 @@ impl Session {
         }
         if self.available_accounts().split(DELIMITER).filter(|a| *a == account_name).count() > 0 { return Err(ValidationError::UserExists); }
 
-        if password.len() <= 2 { return Err(ValidationError::InvalidPassword("Too short".to_string())); }
+        let evaluation = self.password_policy.evaluate(password, &[account_name]);
+        if !evaluation.accepted { return Err(ValidationError::InvalidPassword(evaluation.message())); }
 
         if mnemonic::validate(mnemonic) == false { return Err(ValidationError::InvalidMnemonic); }
 
@@ -361,9 +922,165 @@ impl Session {
         observer.set_account_name(account_name);
     }
 
+    fn remove_account_name(&mut self, account_name: &str) {
+        let mut storage = self.storage.lock().unwrap();
+        let available_accounts: Vec<String> = storage.get(KEY_AVAILABLE_ACCOUNT_NAMES).unwrap_or_default()
+            .split(DELIMITER).map(String::from).filter(|name| name.len() > 0 && name != account_name).collect();
+        let available_accounts_string = available_accounts.join(DELIMITER);
+        storage.set(KEY_AVAILABLE_ACCOUNT_NAMES, &available_accounts_string);
+        self.wallet_observer.lock().unwrap().set_available_accounts(available_accounts_string);
+    }
+
+    /** Verify `password` (or the duress password) while enforcing the back off delay. Returns the profile if successful. 
+        The failed attempts are not reset; use `reset_failed_attempts` once the sign in is complete. */
+    fn authenticate(&mut self, account_name: &str, password: &str) -> Option<Profile> {
+
+        if self.is_throttled(account_name) { return None; }
+
+        // Always try both, so that the time it takes doesn't tell which one matched.
+        let vault = SealedStorage::open(self.storage.clone(), &vault_key(account_name), password)
+            .filter(|sealed| Authentication::check_password(sealed, account_name, password));
+        let password_ok = Authentication::check_password(&*self.storage.lock().unwrap(), account_name, password);
+
+        if password_ok {
+            let storage = self.regular_storage(account_name, &Authentication::new(self.storage.clone(), password));
+            let keychain = Arc::new(Mutex::new(Authentication::new(storage, password)));
+            return Some(Profile { keychain: keychain, sealed: None });
+        }
+        if let Some(vault) = vault {
+            let sealed = Arc::new(Mutex::new(vault));
+            let keychain = Arc::new(Mutex::new(Authentication::new(sealed.clone(), password)));
+            return Some(Profile { keychain: keychain, sealed: Some(sealed) });
+        }
+        self.register_failed_attempt(account_name);
+        None
+    }
+
+    /** Returns `true` if `password` opens either the regular or the duress profile of `account_name`. */
+    fn password_in_use(&self, account_name: &str, password: &str) -> bool {
+        Authentication::check_password(&*self.storage.lock().unwrap(), account_name, password) ||
+            SealedStorage::open(self.storage.clone(), &vault_key(account_name), password)
+                .map_or(false, |sealed| Authentication::check_password(&sealed, account_name, password))
+    }
+
+    fn set_profile(&mut self, profile: Profile) {
+        *self.keychain.get_mut() = Some(profile.keychain);
+        self.sealed = profile.sealed;
+    }
+
+    /** The storage used by the signed in profile. */
+    fn profile_storage(&self) -> Arc<Mutex<dyn Storage>> {
+        match (&self.sealed, &*self.keychain.borrow()) {
+            (Some(sealed), _) => sealed.clone(),
+            (None, Some(keychain)) => self.regular_storage(&self.account_name, &keychain.lock().unwrap()),
+            (None, None) => self.storage.clone()
+        }
+    }
+
+    /** The storage of the regular profile. Changes rewrite the filler, unless the account has a duress profile. */
+    fn regular_storage(&self, account_name: &str, keychain: &Authentication) -> Arc<Mutex<dyn Storage>> {
+        if keychain.get(&vault_state_key(account_name)).as_deref() == Some(VAULT_STATE_FILLER) {
+            return Arc::new(Mutex::new(CoveredStorage::new(self.storage.clone(), &vault_key(account_name))));
+        }
+        self.storage.clone()
+    }
+
+    /** Returns `true` (and notifies JS) if `account_name` has to wait before making another attempt. */
+    fn is_throttled(&self, account_name: &str) -> bool {
+        let storage = self.storage.lock().unwrap();
+        let attempts = SignInAttempts::load(&*storage, account_name);
+
+        let remaining_delay = attempts.remaining_delay(now());
+        if remaining_delay > 0.0 {
+            let attempts_before_wipe = attempts.attempts_before_wipe(&*storage, account_name);
+            signInFailed(attempts.failed, remaining_delay, attempts_before_wipe.map_or(-1, |a| a as i32));
+            errorCallback(&format!("Too many failed attempts. Try again in {} seconds.", remaining_delay.ceil()));
+            return true;
+        }
+        false
+    }
+
+    fn register_failed_attempt(&mut self, account_name: &str) {
+
+        let (attempts, attempts_before_wipe) = {
+            let mut storage = self.storage.lock().unwrap();
+            let mut attempts = SignInAttempts::load(&*storage, account_name);
+            attempts.register_failure(now());
+            attempts.store(&mut *storage, account_name);
+            let attempts_before_wipe = attempts.attempts_before_wipe(&*storage, account_name);
+            (attempts, attempts_before_wipe)
+        };
+
+        if attempts_before_wipe == Some(0) {
+            self.wipe_account(account_name);
+            return;
+        }
+
+        signInFailed(attempts.failed, attempts.remaining_delay(now()), attempts_before_wipe.map_or(-1, |a| a as i32));
+        match attempts_before_wipe {
+            Some(remaining) if remaining <= WIPE_WARNING_THRESHOLD => 
+                errorCallback(&format!("Invalid credentials. The account will be wiped after {} more failed attempt(s)!", remaining)),
+            _ => errorCallback("Invalid credentials")
+        }
+    }
+
+    /** Returns `true` if `code` is a one time password of the second factor newer than the last accepted one.
+        Its time step is stored, so that the code can't be used again. */
+    fn verify_one_time_password(keychain: &mut Authentication, account_name: &str, code: &str) -> bool {
+        let second_factor = match keychain.get(&secret_key(account_name)).and_then(|secret| SecondFactor::from_secret(&secret, account_name).ok()) {
+            Some(second_factor) => second_factor,
+            None => return false
+        };
+        let last_step = keychain.get(&last_step_key(account_name)).and_then(|step| step.parse::<u64>().ok());
+        match second_factor.verify(code, now(), last_step) {
+            Some(step) => {
+                keychain.set(&last_step_key(account_name), &step.to_string());
+                true
+            },
+            None => false
+        }
+    }
+
+    /** Returns `true` if `code` is an unused recovery code, which is then removed. */
+    fn consume_recovery_code(keychain: &mut Authentication, account_name: &str, code: &str) -> bool {
+        let code = normalize_recovery_code(code);
+        let mut recovery_codes: Vec<String> = keychain.get(&recovery_codes_key(account_name))
+            .and_then(|serialized| serde_json::from_str(&serialized).ok())
+            .unwrap_or_default();
+
+        if let Some(index) = recovery_codes.iter().position(|c| *c == code) {
+            recovery_codes.remove(index);
+            let serialized = serde_json::to_string(&recovery_codes).expect("Unable to serialize recovery codes");
+            keychain.set(&recovery_codes_key(account_name), &serialized);
+            log!("Recovery code used. {} remaining.", recovery_codes.len());
+            return true;
+        }
+        false
+    }
+
+    fn reset_failed_attempts(&self, account_name: &str) {
+        SignInAttempts::reset(&mut *self.storage.lock().unwrap(), account_name);
+    }
+
+    /** Delete the credentials of `account_name` after too many failed sign in attempts. */
+    fn wipe_account(&mut self, account_name: &str) {
+        log!("⚠️⚠️⚠️ Too many failed sign in attempts. Wiping keychain for {}! ⚠️⚠️⚠️", account_name);
+        {
+            let mut storage = self.storage.lock().unwrap();
+            wipe_credentials(&mut *storage, &AccountIdentifier { id: account_name.to_string() });
+            SignInAttempts::reset(&mut *storage, account_name);
+            SignInAttempts::set_wipe_after(&mut *storage, account_name, 0);
+        }
+        self.remove_account_name(account_name);
+        self.wallet_observer.lock().unwrap().set_account_name("");
+        self.sign_out();
+        errorCallback("Too many failed attempts. The account has been wiped.");
+    }
+
     fn create_wallet(&mut self, account_name: &str, mnemonic: Option<&str>) {
 
-        if let Some(keychain) = &mut self.keychain {
+        let profile_storage = self.profile_storage();
+        if let Some(keychain) = self.keychain.get_mut() {
 
             let weak_observer_reference = Arc::downgrade(&self.wallet_observer);
 
@@ -371,7 +1088,8 @@ impl Session {
                                 self.base_url.clone(),
                                 account_name, 
                                 keychain.clone(), 
-                                self.storage.clone(),
+                                profile_storage,
+                                self.chains_metadata.clone(),
                                 Some(weak_observer_reference.clone()));
             
             
@@ -391,42 +1109,261 @@ impl Session {
 #[wasm_bindgen]
 impl Session {
 
-    pub async fn transfer(&self, id: String, amount: f32, destination: String) -> bool {
+    /** Broadcast a transfer and return its hash (empty on failure). Status updates are delivered through `transactionStatusChanged`. */
+    pub async fn transfer(&self, id: String, amount: String, destination: String) -> String {
+
+        if self.state.get() == SessionState::Locked {
+            errorCallback("Session is locked!");
+            return "".to_string();
+        }
+        self.touch();
 
         if let Some(wallet_arc) = &self.wallet {
-            let wallet = wallet_arc.lock().unwrap();
+            let amount = match self.wallet_observer.lock().unwrap().parse_amount(&id, &amount) {
+                Ok(amount) => amount,
+                Err(error) => {
+                    errorCallback(&format!("Invalid amount: {}", error));
+                    return "".to_string();
+                }
+            };
+            log!("Will make a transfer of {amount} base units from {id} to {destination}");
+
+            return match Wallet::transfer(wallet_arc, id.clone(), amount, destination).await {
+                Ok(pending_transfer) => self.track_transfer(pending_transfer),
+                Err(error) => {
+                    errorCallback(&format!("Transfer failed: {}", error));
+                    "".to_string()
+                }
+            };
+        }
+        
+        errorCallback("Wallet not initialized!");
+        "".to_string()
+    }
+
+    /** Resend a pending transfer with higher fees. Returns the hash of the replacement, empty on failure. */
+    pub async fn speed_up_transfer(&self, hash: String) -> String {
+        self.replace_transfer(hash, false).await
+    }
+
+    /** Cancel a pending transfer by replacing it with a 0 value transfer to the own address. Returns the hash of the replacement, empty on failure. */
+    pub async fn cancel_transfer(&self, hash: String) -> String {
+        self.replace_transfer(hash, true).await
+    }
+
+    /** Query the transaction history. `filter` is a serialized `HistoryFilter` (may be empty). Returns a serialized `HistoryPage`. */
+    pub fn transaction_history(&self, filter: &str, offset: u32, limit: u32) -> String {
+        let filter: HistoryFilter = if filter.trim().is_empty() {
+            HistoryFilter::default()
+        } else {
+            match serde_json::from_str(filter) {
+                Ok(filter) => filter,
+                Err(error) => {
+                    errorCallback(&format!("Invalid history filter: {}", error));
+                    return "".to_string();
+                }
+            }
+        };
 
-            let denomiator = self.wallet_observer.lock().unwrap().denomiator_for(&id);
-            let amount = ((amount as f64) * denomiator) as u64;
-            log!("Will make a transfer of {amount} gwei from chain {id} to {destination}");
+        if let Some(wallet_arc) = &self.wallet {
+            let page = wallet_arc.lock().unwrap().transaction_history(&filter, offset as usize, limit as usize);
+            return serde_json::to_string(&page).expect("Unable to serialize transaction history");
+        }
 
-            if let Err(error) = wallet.transfer(id.clone(), amount, destination).await {
+        errorCallback("Wallet not initialized!");
+        "".to_string()
+    }
 
-                errorCallback(&format!("Transfer failed: {}", error));
+    /** Set the number of confirmations after which transfers on `chain_id` are reported as confirmed. */
+    pub fn set_confirmation_target(&self, chain_id: &str, confirmation_target: u32) -> bool {
+        if let Some(wallet_arc) = &self.wallet {
+            if let Err(error) = wallet_arc.lock().unwrap().set_confirmation_target(chain_id.to_string(), confirmation_target as u64) {
+                errorCallback(&format!("Unable to set confirmation target: {}", error));
                 return false;
             }
- 
             return true;
         }
-        
         errorCallback("Wallet not initialized!");
         false
     }
 
-    pub async fn add_chain(&mut self, rpc: &str) -> bool {
+    /** Configure the Etherscan compatible explorer API (i.e. "https://api.etherscan.io/api") of `chain_id`.
+     An empty `base_url` removes it. As stored keys aren't handed out, an empty `api_key` keeps the key of the same `base_url`,
+     if any, and otherwise sends no key. */
+    pub fn set_explorer_api(&self, chain_id: &str, base_url: &str, api_key: &str) -> bool {
+        let api = match base_url.trim() {
+            "" => None,
+            base_url => {
+                let api_key = match api_key.trim() {
+                    "" => self.wallet.as_ref()
+                        .and_then(|wallet_arc| wallet_arc.lock().unwrap().explorer_api(chain_id))
+                        .filter(|api| api.base_url == base_url)
+                        .and_then(|api| api.api_key),
+                    api_key => Some(api_key.to_string())
+                };
+                Some(ExplorerApi { base_url: base_url.to_string(), api_key: api_key })
+            }
+        };
+        if let Some(wallet_arc) = &self.wallet {
+            if let Err(error) = wallet_arc.lock().unwrap().set_explorer_api(chain_id.to_string(), api) {
+                errorCallback(&format!("Unable to set explorer API: {}", error));
+                return false;
+            }
+            let wallet = wallet_arc.lock().unwrap();
+            self.wallet_observer.lock().unwrap().set_chains(wallet.chains(), wallet.disabled_chains());
+            return true;
+        }
+        errorCallback("Wallet not initialized!");
+        false
+    }
+
+    /** Define or override the name, native symbol, decimals, explorer URL (or template with `{kind}` and `{value}`) and testnet flag
+     of `chain_id`. Empty or missing values keep those of the downloaded metadata. */
+    pub fn set_network_overrides(&self, chain_id: &str, name: Option<String>, symbol: Option<String>, decimals: Option<u32>,
+                                 explorer_url: Option<String>, testnet: Option<bool>) -> bool {
+        let text = |value: Option<String>| value.map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        let overrides = NetworkOverrides { name: text(name),
+                                           symbol: text(symbol),
+                                           decimals: decimals,
+                                           explorer_url: text(explorer_url),
+                                           testnet: testnet };
+        if let Some(wallet_arc) = &self.wallet {
+            if let Err(error) = wallet_arc.lock().unwrap().set_network_overrides(chain_id.trim().to_string(), overrides) {
+                errorCallback(&format!("Unable to set network properties: {}", error));
+                return false;
+            }
+            let wallet = wallet_arc.lock().unwrap();
+            self.wallet_observer.lock().unwrap().set_chains(wallet.chains(), wallet.disabled_chains());
+            return true;
+        }
+        errorCallback("Wallet not initialized!");
+        false
+    }
+
+    /** Network properties of `chain_id` defined by the user. Returns a serialized `NetworkOverrides`, empty on failure. */
+    pub fn network_overrides(&self, chain_id: &str) -> String {
+        match &self.wallet {
+            Some(wallet_arc) => serde_json::to_string(&wallet_arc.lock().unwrap().network_overrides(chain_id.trim()))
+                .expect("Unable to serialize network overrides"),
+            None => {
+                errorCallback("Wallet not initialized!");
+                "".to_string()
+            }
+        }
+    }
+
+    /** Import past transfers of `chain_id` from its explorer API into the history. Returns the number of new records, -1 on failure. */
+    pub async fn import_history(&self, chain_id: String) -> i32 {
+        let chain = match &self.wallet {
+            Some(wallet_arc) => wallet_arc.lock().unwrap().portfolio_chain(&chain_id),
+            None => {
+                errorCallback("Wallet not initialized!");
+                return -1;
+            }
+        };
+        let chain = match chain {
+            Some(chain) => chain,
+            None => {
+                errorCallback(&format!("Chain id {} not found", chain_id));
+                return -1;
+            }
+        };
+
+        match chain.import_history().await {
+            Ok(count) => count as i32,
+            Err(error) => {
+                errorCallback(&format!("Unable to import history: {}", error));
+                -1
+            }
+        }
+    }
+
+    /** Estimate the fees of a transfer. Returns a serialized `TransferPreviewDescriptor`, empty on failure. */
+    pub async fn preview_transfer(&self, id: String, amount: String, destination: String) -> String {
+
+        if self.state.get() == SessionState::Locked {
+            errorCallback("Session is locked!");
+            return "".to_string();
+        }
+        self.touch();
+
+        if let Some(wallet_arc) = &self.wallet {
+            let amount = match self.wallet_observer.lock().unwrap().parse_amount(&id, &amount) {
+                Ok(amount) => amount,
+                Err(error) => {
+                    errorCallback(&format!("Invalid amount: {}", error));
+                    return "".to_string();
+                }
+            };
+
+            return match Wallet::preview_transfer(wallet_arc, id, amount, destination).await {
+                Ok(preview) => serde_json::to_string(&preview).unwrap_or_default(),
+                Err(error) => {
+                    errorCallback(&format!("Unable to estimate fees: {}", error));
+                    "".to_string()
+                }
+            };
+        }
+
+        errorCallback("Wallet not initialized!");
+        "".to_string()
+    }
+
+    /** Send a previewed transfer using the fees of `speed` ("slow", "normal" or "fast"). Returns the hash, empty on failure. */
+    pub async fn confirm_transfer(&self, preview_id: u32, speed: &str) -> String {
+
+        if self.state.get() == SessionState::Locked {
+            errorCallback("Session is locked!");
+            return "".to_string();
+        }
+        self.touch();
+
+        if let Some(wallet_arc) = &self.wallet {
+            log!("Will send previewed transfer {preview_id} with {speed} fees");
+
+            return match Wallet::confirm_transfer(wallet_arc, preview_id, speed).await {
+                Ok(pending_transfer) => self.track_transfer(pending_transfer),
+                Err(error) => {
+                    errorCallback(&format!("Transfer failed: {}", error));
+                    "".to_string()
+                }
+            };
+        }
+
+        errorCallback("Wallet not initialized!");
+        "".to_string()
+    }
+
+    pub fn discard_transfer_preview(&self, preview_id: u32) {
+        if let Some(wallet_arc) = &self.wallet {
+            wallet_arc.lock().unwrap().discard_preview(preview_id);
+        }
+    }
+
+    /** Add the chain served by `rpc`. A non-empty `expected_chain_id` refuses endpoints serving another chain. */
+    pub async fn add_chain(&mut self, rpc: &str, expected_chain_id: &str) -> bool {
 
         let success: bool;
         let mut chain_id: String = "".to_string();
+        self.touch();
+        let expected_chain_id = match expected_chain_id.trim() {
+            "" => None,
+            expected => match expected.parse::<u64>() {
+                Ok(expected) => Some(expected),
+                Err(_) => {
+                    errorCallback(&format!("Invalid chain id: {}", expected));
+                    return false;
+                }
+            }
+        };
         if let Some(wallet_arc) = &self.wallet {
 
-            let mut wallet = wallet_arc.lock().unwrap();
-
-            if wallet.stored_rpcs().contains(&rpc.to_string()) {
-                errorCallback("Can't add duplicate chain.");
+            if wallet_arc.lock().unwrap().stored_rpcs().contains(&rpc.to_string()) {
+                errorCallback("Can't add duplicate RPC endpoint.");
                 return false;
             }
 
-            success = match wallet.borrow_mut().add_chain(rpc.to_string()).await {
+            success = match Wallet::add_chain(wallet_arc, rpc.to_string(), expected_chain_id).await {
                 Ok(_chain_id) => {
                     chain_id = _chain_id;
                     true
@@ -439,7 +1376,7 @@ impl Session {
             if success {
                 log!("Added chain with id: {}", chain_id);
                 
-                if let Err(error) = wallet.update_balance(chain_id.clone()).await {
+                if let Err(error) = Wallet::update_balance(wallet_arc, chain_id.clone()).await {
                     errorCallback(&format!("Unable fetch balance for chain {}: {:?}", chain_id, error));
                 }
             }
@@ -451,17 +1388,189 @@ impl Session {
 
         if success {
             let mut observer = self.wallet_observer.lock().unwrap();
-            observer.set_chains(self.chains());
+            observer.set_chains(self.chains(), self.disabled_chains());
+            self.subscribe_balances();
         }
     
         success
     }
 
+    /** Find chains by id, name or native symbol. Returns a serialized list of `ChainCandidateDescriptor`. */
+    pub fn search_chains(&self, query: &str) -> String {
+        let candidates = match &self.wallet {
+            Some(wallet_arc) => wallet_arc.lock().unwrap().search_chains(query, MAX_CHAIN_CANDIDATES),
+            None => vec![]
+        };
+        serde_json::to_string(&candidates).expect("Unable to serialize chain candidates")
+    }
+
+    /** Add a chain found by `search_chains`, trying its public RPC endpoints in order. */
+    pub async fn add_chain_by_id(&mut self, chain_id: &str) -> bool {
+
+        self.touch();
+        let chain_id = match chain_id.trim().parse::<u64>() {
+            Ok(chain_id) => chain_id,
+            Err(_) => {
+                errorCallback(&format!("Invalid chain id: {}", chain_id));
+                return false;
+            }
+        };
+        let wallet_arc = match &self.wallet {
+            Some(wallet_arc) => wallet_arc.clone(),
+            None => {
+                errorCallback("Wallet not initialized!");
+                return false;
+            }
+        };
+
+        if wallet_arc.lock().unwrap().portfolio_chain(&chain_id.to_string()).is_some() {
+            errorCallback(&format!("Chain {} has already been added.", chain_id));
+            return false;
+        }
+        match Wallet::add_chain_by_id(&wallet_arc, chain_id).await {
+            Ok(chain_id) => {
+                log!("Added chain with id: {}", chain_id);
+                if let Err(error) = Wallet::update_balance(&wallet_arc, chain_id.clone()).await {
+                    errorCallback(&format!("Unable fetch balance for chain {}: {:?}", chain_id, error));
+                }
+            },
+            Err(error) => {
+                errorCallback(&format!("Unable to add chain: {}", error));
+                return false;
+            }
+        }
+
+        let mut observer = self.wallet_observer.lock().unwrap();
+        observer.set_chains(self.chains(), self.disabled_chains());
+        self.subscribe_balances();
+        true
+    }
+
+    /** Remove `chain_id` and its tokens from the portfolio. */
+    pub fn remove_chain(&mut self, chain_id: &str) -> bool {
+        self.touch();
+        self.change_portfolio(|wallet| wallet.remove_chain(chain_id.to_string()), "Unable to remove chain")
+    }
+
+    /** Remove the token of `contract_address` from `chain_id`. */
+    pub fn remove_token(&mut self, chain_id: &str, contract_address: &str) -> bool {
+        self.touch();
+        self.change_portfolio(|wallet| wallet.remove_token(chain_id.to_string(), contract_address.to_string()), "Unable to remove token")
+    }
+
+    /** Disable `chain_id`, keeping its configuration and tokens, or enable it again. Disabled chains aren't loaded,
+     refreshed or scanned. */
+    pub async fn set_chain_enabled(&mut self, chain_id: &str, enabled: bool) -> bool {
+        self.touch();
+        let wallet_arc = match &self.wallet {
+            Some(wallet_arc) => wallet_arc.clone(),
+            None => {
+                errorCallback("Wallet not initialized!");
+                return false;
+            }
+        };
+
+        if let Err(error) = Wallet::set_chain_enabled(&wallet_arc, chain_id.to_string(), enabled).await {
+            errorCallback(&format!("Unable to {} chain: {}", if enabled { "enable" } else { "disable" }, error));
+            return false;
+        }
+        if enabled {
+            if let Err(error) = Wallet::update_balance(&wallet_arc, chain_id.to_string()).await {
+                errorCallback(&format!("Unable fetch balance for chain {}: {:?}", chain_id, error));
+            }
+        }
+
+        let mut observer = self.wallet_observer.lock().unwrap();
+        observer.set_chains(self.chains(), self.disabled_chains());
+        self.subscribe_balances();
+        true
+    }
+
+    /** Check an RPC endpoint before adding it. Returns a serialized `RpcDiagnostics`. */
+    pub async fn diagnose_rpc(&self, rpc: String) -> String {
+        let diagnostics = diagnose(&rpc).await;
+        serde_json::to_string(&diagnostics).expect("Unable to serialize RPC diagnostics")
+    }
+
+    /** Check the RPC endpoints of `chain_id`. Returns a serialized list of `RpcDiagnostics`, empty on failure. */
+    pub async fn chain_diagnostics(&self, chain_id: String) -> String {
+        let chain = match &self.wallet {
+            Some(wallet_arc) => wallet_arc.lock().unwrap().portfolio_chain(&chain_id),
+            None => {
+                errorCallback("Wallet not initialized!");
+                return "".to_string();
+            }
+        };
+        match chain {
+            Some(chain) => serde_json::to_string(&chain.diagnose_rpcs().await).expect("Unable to serialize RPC diagnostics"),
+            None => {
+                errorCallback(&format!("Chain id {} not found", chain_id));
+                "".to_string()
+            }
+        }
+    }
+
+    /** Explorer page of an address or token contract on `chain_id`. Empty if the chain has no known explorer. */
+    pub fn explorer_address_url(&self, chain_id: &str, address: &str) -> String {
+        self.chain_info(chain_id).and_then(|info| info.address_url(address)).unwrap_or_default()
+    }
+
+    /** Explorer page of a transaction on `chain_id`. Empty if the chain has no known explorer. */
+    pub fn explorer_transaction_url(&self, chain_id: &str, hash: &str) -> String {
+        self.chain_info(chain_id).and_then(|info| info.transaction_url(hash)).unwrap_or_default()
+    }
+
+    /** Add a fallback RPC endpoint to `chain_id`. It has to serve the same chain. */
+    pub async fn add_rpc(&mut self, chain_id: &str, rpc: &str) -> bool {
+        self.touch();
+        if let Some(wallet_arc) = &self.wallet {
+            if let Err(error) = Wallet::add_rpc(wallet_arc, chain_id.to_string(), rpc.to_string()).await {
+                errorCallback(&format!("Unable to add RPC endpoint: {}", error));
+                return false;
+            }
+            self.wallet_observer.lock().unwrap().set_chains(self.chains(), self.disabled_chains());
+            self.subscribe_balances();
+            return true;
+        }
+        errorCallback("Wallet not initialized!");
+        false
+    }
+
+    pub fn remove_rpc(&mut self, chain_id: &str, rpc: &str) -> bool {
+        self.touch();
+        if let Some(wallet_arc) = &self.wallet {
+            if let Err(error) = wallet_arc.lock().unwrap().remove_rpc(chain_id.to_string(), rpc.to_string()) {
+                errorCallback(&format!("Unable to remove RPC endpoint: {}", error));
+                return false;
+            }
+            self.wallet_observer.lock().unwrap().set_chains(self.chains(), self.disabled_chains());
+            self.subscribe_balances();
+            return true;
+        }
+        errorCallback("Wallet not initialized!");
+        false
+    }
+
+    /** Require `quorum` RPC endpoints of `chain_id` to agree on balances and receipts. 0 or 1 trusts the first answer. */
+    pub fn set_quorum(&self, chain_id: &str, quorum: u32) -> bool {
+        if let Some(wallet_arc) = &self.wallet {
+            let quorum = if quorum > 1 { Some(quorum as usize) } else { None };
+            if let Err(error) = wallet_arc.lock().unwrap().set_quorum(chain_id.to_string(), quorum) {
+                errorCallback(&format!("Unable to set quorum: {}", error));
+                return false;
+            }
+            self.wallet_observer.lock().unwrap().set_chains(self.chains(), self.disabled_chains());
+            return true;
+        }
+        errorCallback("Wallet not initialized!");
+        false
+    }
+
     pub async fn add_token(&mut self, chain_id: &str, contract_address: &str, symbol: &str, decimals: u32) -> bool {
         let mut success: bool = false;
+        self.touch();
         if let Some(wallet_ref) = &self.wallet {
-            success = match wallet_ref.lock().unwrap()
-                                    .add_token(chain_id.to_string(), contract_address.to_string(), symbol.to_string(), Some(decimals)).await {
+            success = match Wallet::add_token(wallet_ref, chain_id.to_string(), contract_address.to_string(), symbol.to_string(), Some(decimals)).await {
                 Ok(()) => true,
                 Err(error) => {
                     errorCallback(&format!("Unable to add token: {:?}", error));
@@ -474,12 +1583,240 @@ impl Session {
 
         if success {
             let mut observer = self.wallet_observer.lock().unwrap();
-            observer.set_chains(self.chains());
+            observer.set_chains(self.chains(), self.disabled_chains());
+            self.subscribe_balances();
         }
 
         success
     }
 
+    async fn replace_transfer(&self, hash: String, cancel: bool) -> String {
+
+        if self.state.get() == SessionState::Locked {
+            errorCallback("Session is locked!");
+            return "".to_string();
+        }
+        self.touch();
+
+        if let Some(wallet_arc) = &self.wallet {
+            log!("Will {} transfer {hash}", if cancel { "cancel" } else { "speed up" });
+
+            let result = if cancel { Wallet::cancel(wallet_arc, hash).await } else { Wallet::speed_up(wallet_arc, hash).await };
+            return match result {
+                Ok(pending_transfer) => self.track_transfer(pending_transfer),
+                Err(error) => {
+                    errorCallback(&format!("Unable to replace transfer: {}", error));
+                    "".to_string()
+                }
+            };
+        }
+
+        errorCallback("Wallet not initialized!");
+        "".to_string()
+    }
+
+    /** Apply `change` to the wallet, then refresh the chains of the observer and the balance subscriptions. */
+    fn change_portfolio<F>(&self, change: F, failure: &str) -> bool
+            where F: FnOnce(&mut Wallet<Authentication>) -> blockchain::shared::Result<()> {
+        let wallet_arc = match &self.wallet {
+            Some(wallet_arc) => wallet_arc,
+            None => {
+                errorCallback("Wallet not initialized!");
+                return false;
+            }
+        };
+        let (chains, disabled_chains) = {
+            let mut wallet = wallet_arc.lock().unwrap();
+            if let Err(error) = change(&mut wallet) {
+                errorCallback(&format!("{}: {}", failure, error));
+                return false;
+            }
+            (wallet.chains(), wallet.disabled_chains())
+        };
+        self.wallet_observer.lock().unwrap().set_chains(chains, disabled_chains);
+        self.subscribe_balances();
+        true
+    }
+
+    /** (Re)start pushing balance changes of the chains with a WebSocket endpoint, replacing running subscriptions. */
+    fn subscribe_balances(&self) {
+        let chains = match &self.wallet {
+            Some(wallet_arc) => wallet_arc.lock().unwrap().portfolio_chains(),
+            None => return
+        };
+        for subscription in chains.iter().filter_map(|chain| chain.subscribe_balances()) {
+            wasm_bindgen_futures::spawn_local(subscription.run());
+        }
+    }
+
+    /** Download the chains metadata in the background if the cached list is outdated. Chains added afterwards use the new list. */
+    fn refresh_chains_metadata(&self) {
+        if !self.chains_metadata.is_stale() {
+            return;
+        }
+        let chains_metadata = self.chains_metadata.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(error) = chains_metadata.refresh().await {
+                log!("Unable to refresh chains metadata: {}", error);
+            }
+        });
+    }
+
+    /** Try to connect the offline chains that are due for another attempt in the background. Call periodically. */
+    pub fn retry_offline_chains(&self) {
+        let wallet_arc = match &self.wallet {
+            Some(wallet_arc) => wallet_arc.clone(),
+            None => return
+        };
+        let (configs, tokens) = {
+            let mut wallet = wallet_arc.lock().unwrap();
+            (wallet.offline_chains_to_retry(), wallet.offline_tokens_to_retry())
+        };
+        if configs.is_empty() && tokens.is_empty() {
+            return;
+        }
+        let wallet = Arc::downgrade(&wallet_arc);
+        let observer = self.wallet_observer.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            for config in configs {
+                // Probe without holding the wallet, connecting is only attempted once an endpoint serves the chain.
+                let mut reachable = false;
+                for rpc in config.rpcs.iter() {
+                    let diagnostics = diagnose(rpc).await;
+                    if diagnostics.reachable && diagnostics.chain_id.map(|id| id.to_string()) == Some(config.chain_id.clone()) {
+                        reachable = true;
+                        break;
+                    }
+                }
+                if !reachable { continue; }
+
+                // Connect without holding the wallet, which is only locked briefly to attach the chain.
+                let connection = match wallet.upgrade() {
+                    Some(wallet_arc) => wallet_arc.lock().unwrap().connect_offline_chain(&config.chain_id),
+                    // Signed out meanwhile.
+                    None => return
+                };
+                let connection = match connection {
+                    Some(connection) => connection,
+                    // Removed, disabled or added again meanwhile.
+                    None => continue
+                };
+                let result = connection.await;
+
+                let wallet_arc = match wallet.upgrade() {
+                    Some(wallet_arc) => wallet_arc,
+                    None => return
+                };
+                let (chain, error) = {
+                    let mut wallet = wallet_arc.lock().unwrap();
+                    let outcome = match result {
+                        Ok(connected) => if wallet.attach_chain(connected) {
+                            (wallet.portfolio_chain(&config.chain_id), None)
+                        } else {
+                            (None, None)
+                        },
+                        Err(error) => {
+                            wallet.set_offline_error(&config.chain_id, error.to_string());
+                            (None, Some(error.to_string()))
+                        }
+                    };
+                    observer.lock().unwrap().set_chains(wallet.chains(), wallet.disabled_chains());
+                    outcome
+                };
+
+                if let Some(chain) = chain {
+                    log!("Chain {} is online again.", config.chain_id);
+                    if let Err(error) = chain.update_balance().await {
+                        log!("Unable to update balance of chain {}: {}", config.chain_id, error);
+                    }
+                    for token in chain.tokens().iter() {
+                        if let Err(error) = token.update_balance().await {
+                            log!("Unable to update balance of token {}: {}", token.identifier(), error);
+                        }
+                    }
+                    if let Some(subscription) = chain.subscribe_balances() {
+                        wasm_bindgen_futures::spawn_local(subscription.run());
+                    }
+                    chainStatusChanged(&config.chain_id, "");
+                } else if let Some(error) = error {
+                    log!("Unable to reconnect chain {}: {}", config.chain_id, error);
+                    chainStatusChanged(&config.chain_id, &error);
+                }
+            }
+
+            for (chain_id, token_descriptor) in tokens {
+                let chain = match wallet.upgrade() {
+                    Some(wallet_arc) => wallet_arc.lock().unwrap().portfolio_chain(&chain_id),
+                    None => return
+                };
+                let chain = match chain {
+                    Some(chain) => chain,
+                    None => continue
+                };
+                // The token's observers are those of the chain, its balance is reported once it's connected.
+                let result = chain.connect_token(
+                    token_descriptor.contract_address.clone(), 
+                    token_descriptor.symbol.clone(), 
+                    Some(token_descriptor.decimals)).await;
+
+                let wallet_arc = match wallet.upgrade() {
+                    Some(wallet_arc) => wallet_arc,
+                    None => return
+                };
+                let attached = {
+                    let mut wallet = wallet_arc.lock().unwrap();
+                    let attached = match result {
+                        Ok(token) => wallet.attach_token(&chain_id, token),
+                        Err(error) => {
+                            log!("Unable to load token {} of chain {}: {}", token_descriptor.symbol, chain_id, error);
+                            wallet.set_offline_token_error(&chain_id, &token_descriptor.contract_address, error.to_string());
+                            false
+                        }
+                    };
+                    observer.lock().unwrap().set_chains(wallet.chains(), wallet.disabled_chains());
+                    attached
+                };
+                if attached {
+                    log!("Token {} of chain {} is loaded.", token_descriptor.symbol, chain_id);
+                    chainStatusChanged(&chain_id, "");
+                }
+            }
+        });
+    }
+
+    /** Scan the blocks mined since the last scan for incoming transfers in the background. Call periodically. */
+    pub fn scan_incoming_transfers(&self) {
+        let chains = match &self.wallet {
+            Some(wallet_arc) => wallet_arc.lock().unwrap().portfolio_chains(),
+            None => return
+        };
+        let observer: Weak<Mutex<ReceivedObserver>> = Arc::downgrade(&self.wallet_observer) as Weak<Mutex<ReceivedObserver>>;
+        wasm_bindgen_futures::spawn_local(async move {
+            for chain in chains.iter() {
+                match chain.scan_incoming(Some(observer.clone())).await {
+                    Ok(0) => {},
+                    Ok(count) => { log!("Received {} transfers on chain {}", count, chain.identifier()); },
+                    Err(error) => { log!("Unable to scan chain {}: {}", chain.identifier(), error); }
+                }
+            }
+        });
+    }
+
+    /** Follow a broadcast transfer in the background and return its hash. */
+    fn track_transfer(&self, pending_transfer: PendingTransfer) -> String {
+        let hash = pending_transfer.hash();
+        let observer: Weak<Mutex<StatusObserver>> = Arc::downgrade(&self.wallet_observer) as Weak<Mutex<StatusObserver>>;
+        wasm_bindgen_futures::spawn_local(async move {
+            let status = pending_transfer.track(Some(observer)).await;
+            log!("Transfer {} is {}", pending_transfer.hash(), status.name());
+        });
+        hash
+    }
+
+    fn chain_info(&self, chain_id: &str) -> Option<ChainInfo> {
+        self.wallet.as_ref()?.lock().unwrap().chain_info(chain_id)
+    }
+
     fn chains(&self) -> Vec<ChainDescriptor> {
         if let Some(wallet_ref) = &self.wallet {
             let chains = &wallet_ref.lock().unwrap().chains();
@@ -488,6 +1825,13 @@ impl Session {
         panic!("Wallet not initialized");
     }
 
+    fn disabled_chains(&self) -> Vec<DisabledChainDescriptor> {
+        match &self.wallet {
+            Some(wallet_arc) => wallet_arc.lock().unwrap().disabled_chains(),
+            None => vec![]
+        }
+    }
+
     fn tokens(&self, chain_id: &str) -> Vec<TokenDescriptor> {
         if let Some(wallet_ref) = &self.wallet {
             let tokens = &wallet_ref.lock().unwrap().tokens(chain_id.to_string());
@@ -500,7 +1844,7 @@ impl Session {
 
         if let Some(wallet_ref) = &self.wallet {
 
-            match wallet_ref.lock().unwrap().update_balances().await {
+            match Wallet::update_balances(wallet_ref).await {
                 Ok(()) => return true,
                 Err(error) => {
                     errorCallback(&format!("Unable to update balances: {:?}", error));
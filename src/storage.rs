@@ -1,9 +1,13 @@
 extern crate web_sys;
 
 use magic_crypt::{new_magic_crypt, MagicCryptTrait, MagicCrypt256};
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 //use wasm_bindgen::prelude::*;
 
+use crate::log;
+
 /// Represent an entity capable of storing key-value pairs. 
 pub trait Storage {
 
@@ -21,13 +25,18 @@ pub trait Storage {
 }
 
 /// A `Storage` implementation where the values are encrypted.
-#[derive(std::fmt::Debug)]
-pub struct KeyChain<T> where T: Storage {
+pub struct KeyChain<T> where T: Storage + ?Sized {
     storage: Arc<Mutex<T>>,
     encryption: MagicCrypt256
 }
 
-impl<T> KeyChain<T> where T: Storage {
+impl<T> std::fmt::Debug for KeyChain<T> where T: Storage + ?Sized {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "KeyChain")
+    }
+}
+
+impl<T> KeyChain<T> where T: Storage + ?Sized {
     
     pub fn new(storage: Arc<Mutex<T>>, password: &str) -> Self {
         Self { encryption: new_magic_crypt!(password, 256), storage: storage }
@@ -57,7 +66,7 @@ impl<T> KeyChain<T> where T: Storage {
 
 use std::borrow::BorrowMut;
 
-impl<T> Storage for KeyChain<T> where T: Storage {
+impl<T> Storage for KeyChain<T> where T: Storage + ?Sized {
     
     fn get(&self, key: &str) -> Option<String> {
 
@@ -88,6 +97,168 @@ impl<T> Storage for KeyChain<T> where T: Storage {
     }
 }
 
+/// Size (in characters) that the content of a `SealedStorage` is padded to. Changes that don't fit are rejected,
+/// the size of a sealed storage would otherwise tell how much it holds.
+const SEALED_STORAGE_SIZE: usize = 65536;
+/// Prefixed to the password before deriving the sealing key, so that it differs from the key of a `KeyChain` using the same password.
+const SEALED_STORAGE_KEY_PREFIX: &str = "$SEALED$";
+/// Length of the random prefix of the content. The encryption is deterministic, without it an unchanged beginning
+/// of the content would show in the value, unlike in a rewritten filler.
+const SEALED_STORAGE_NONCE_LENGTH: usize = 32;
+
+/// A `Storage` keeping all of its entries in a single encrypted value of an underlying storage.
+/// The content is padded to `SEALED_STORAGE_SIZE`, making an empty, a used and a filler (see `write_filler`) 
+/// value indistinguishable for anyone without the password.
+pub struct SealedStorage {
+    storage: Arc<Mutex<dyn Storage>>,
+    key: String,
+    encryption: MagicCrypt256,
+    entries: HashMap<String, String>
+}
+
+impl std::fmt::Debug for SealedStorage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "SealedStorage [{}]", self.key)
+    }
+}
+
+unsafe impl Send for SealedStorage {}
+
+impl SealedStorage {
+
+    /// Create an empty sealed storage, replacing anything stored under `key`.
+    pub fn create(storage: Arc<Mutex<dyn Storage>>, key: &str, password: &str) -> Self {
+        let sealed = Self { storage: storage, key: key.to_string(), encryption: Self::encryption(password), entries: HashMap::new() };
+        sealed.seal();
+        sealed
+    }
+
+    /// Decrypt the value stored under `key`. Returns `None` if there's no value or if `password` is invalid.
+    pub fn open(storage: Arc<Mutex<dyn Storage>>, key: &str, password: &str) -> Option<Self> {
+        let encrypted = storage.lock().unwrap().get(key)?;
+        let encryption = Self::encryption(password);
+        let decrypted = encryption.decrypt_base64_to_string(&encrypted).ok()?;
+        let entries: HashMap<String, String> = serde_json::from_str(decrypted.trim_start_matches(|c: char| c.is_ascii_alphanumeric())).ok()?;
+        Some(Self { storage: storage, key: key.to_string(), encryption: encryption, entries: entries })
+    }
+
+    /// Store random data, indistinguishable from a sealed storage, under `key`.
+    pub fn write_filler(storage: &mut dyn Storage, key: &str) {
+        let password = random_string(32);
+        let content = Self::pad(String::new()).expect("Empty sealed storage must fit");
+        storage.set(key, &Self::encryption(&password).encrypt_str_to_base64(content));
+    }
+
+    /// Seal the unchanged content again, changing the stored value like a rewritten filler.
+    pub fn reseal(&self) {
+        self.seal();
+    }
+
+    /// Re-encrypt the content using `password`.
+    pub fn rekey(&mut self, password: &str) {
+        self.encryption = Self::encryption(password);
+        self.seal();
+    }
+
+    fn encryption(password: &str) -> MagicCrypt256 {
+        new_magic_crypt!(format!("{}{}", SEALED_STORAGE_KEY_PREFIX, password), 256)
+    }
+
+    /// Prefix `content` with a random nonce and pad it. Returns `None` if it's larger than `SEALED_STORAGE_SIZE`.
+    fn pad(content: String) -> Option<String> {
+        let mut padded = random_string(SEALED_STORAGE_NONCE_LENGTH);
+        padded.push_str(&content);
+        if padded.len() > SEALED_STORAGE_SIZE { return None; }
+        padded.extend(std::iter::repeat(' ').take(SEALED_STORAGE_SIZE - padded.len()));
+        Some(padded)
+    }
+
+    /// Returns `false` (and stores nothing) if the entries don't fit.
+    fn seal(&self) -> bool {
+        let content = serde_json::to_string(&self.entries).expect("Unable to serialize sealed storage");
+        match Self::pad(content) {
+            Some(content) => {
+                let encrypted = self.encryption.encrypt_str_to_base64(content);
+                self.storage.lock().unwrap().set(&self.key, &encrypted);
+                true
+            },
+            None => false
+        }
+    }
+}
+
+fn random_string(length: usize) -> String {
+    OsRng.sample_iter(&Alphanumeric).take(length).map(char::from).collect()
+}
+
+impl Storage for SealedStorage {
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.entries.get(key).cloned()
+    }
+
+	fn set(&mut self, key: &str, value: &str) {
+        let previous = self.entries.insert(key.to_string(), value.to_string());
+        if !self.seal() {
+            match previous {
+                Some(previous) => self.entries.insert(key.to_string(), previous),
+                None => self.entries.remove(key)
+            };
+            log!("Sealed storage is full, unable to save '{}'.", key);
+        }
+    }
+
+    fn delete(&mut self, key: &str) {
+        self.entries.remove(key);
+        self.seal();
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.seal();
+    }
+}
+
+/// The storage of the regular profile of an account whose `SealedStorage` is only a filler. The filler is rewritten
+/// on every change, just like a duress profile reseals its storage, so that a changed vault doesn't tell which
+/// profile was used.
+pub struct CoveredStorage {
+    storage: Arc<Mutex<dyn Storage>>,
+    vault_key: String
+}
+
+unsafe impl Send for CoveredStorage {}
+
+impl CoveredStorage {
+
+    pub fn new(storage: Arc<Mutex<dyn Storage>>, vault_key: &str) -> Self {
+        Self { storage: storage, vault_key: vault_key.to_string() }
+    }
+}
+
+impl Storage for CoveredStorage {
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.storage.lock().unwrap().get(key)
+    }
+
+	fn set(&mut self, key: &str, value: &str) {
+        let mut storage = self.storage.lock().unwrap();
+        storage.set(key, value);
+        SealedStorage::write_filler(&mut *storage, &self.vault_key);
+    }
+
+    fn delete(&mut self, key: &str) {
+        let mut storage = self.storage.lock().unwrap();
+        storage.delete(key);
+        SealedStorage::write_filler(&mut *storage, &self.vault_key);
+    }
+
+    fn clear(&mut self) {
+        self.storage.lock().unwrap().clear();
+    }
+}
+
 #[derive(std::fmt::Debug)]
 pub struct DefaultStorage {
     internal_storage: Mutex<web_sys::Storage>
@@ -120,3 +291,63 @@ impl Storage for DefaultStorage {
         self.internal_storage.lock().unwrap().clear().expect("Unable to clear web_sys::Storage");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryStorage(HashMap<String, String>);
+
+    impl Storage for MemoryStorage {
+        fn get(&self, key: &str) -> Option<String> { self.0.get(key).cloned() }
+        fn set(&mut self, key: &str, value: &str) { self.0.insert(key.to_string(), value.to_string()); }
+        fn delete(&mut self, key: &str) { self.0.remove(key); }
+        fn clear(&mut self) { self.0.clear(); }
+    }
+
+    fn stored(storage: &Arc<Mutex<MemoryStorage>>, key: &str) -> String {
+        storage.lock().unwrap().get(key).unwrap()
+    }
+
+    #[test]
+    fn sealed_storage_and_filler_have_the_same_size() {
+        let storage = Arc::new(Mutex::new(MemoryStorage::default()));
+        SealedStorage::write_filler(&mut *storage.lock().unwrap(), "filler");
+        let mut sealed = SealedStorage::create(storage.clone(), "vault", "password");
+        assert_eq!(stored(&storage, "vault").len(), stored(&storage, "filler").len());
+
+        sealed.set("history", &"x".repeat(SEALED_STORAGE_SIZE / 2));
+        assert_eq!(stored(&storage, "vault").len(), stored(&storage, "filler").len());
+    }
+
+    #[test]
+    fn sealed_storage_reopens_and_reseals() {
+        let storage = Arc::new(Mutex::new(MemoryStorage::default()));
+        let mut sealed = SealedStorage::create(storage.clone(), "vault", "password");
+        sealed.set("key", "value");
+        let before = stored(&storage, "vault");
+        sealed.reseal();
+        assert_ne!(stored(&storage, "vault"), before);
+
+        let reopened = SealedStorage::open(storage.clone(), "vault", "password").unwrap();
+        assert_eq!(reopened.get("key").as_deref(), Some("value"));
+    }
+
+    #[test]
+    fn covered_storage_rewrites_the_filler() {
+        let storage = Arc::new(Mutex::new(MemoryStorage::default()));
+        SealedStorage::write_filler(&mut *storage.lock().unwrap(), "vault");
+        let mut covered = CoveredStorage::new(storage.clone(), "vault");
+
+        let before = stored(&storage, "vault");
+        covered.set("key", "value");
+        assert_eq!(covered.get("key").as_deref(), Some("value"));
+        assert_ne!(stored(&storage, "vault"), before);
+
+        let before = stored(&storage, "vault");
+        covered.delete("key");
+        assert_eq!(covered.get("key"), None);
+        assert_ne!(stored(&storage, "vault"), before);
+    }
+}
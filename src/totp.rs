@@ -0,0 +1,129 @@
+use rand::rngs::OsRng;
+use rand::RngCore;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const ISSUER: &str = "wasm-wallet";
+const DIGITS: usize = 6;
+/// Number of time steps before and after the current one that are accepted (clock skew tolerance).
+const SKEW: u8 = 1;
+const STEP: u64 = 30;
+const SECRET_LENGTH: usize = 20;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+const KEY_TOTP_SECRET_POSTFIX: &str = "_$TOTP_SECRET";
+const KEY_TOTP_RECOVERY_CODES_POSTFIX: &str = "_$TOTP_RECOVERY_CODES";
+const KEY_TOTP_LAST_STEP_POSTFIX: &str = "_$TOTP_LAST_STEP";
+
+/// Keychain key for the (base32 encoded) TOTP secret of an account.
+pub fn secret_key(account_name: &str) -> String {
+    format!("{}{}", account_name, KEY_TOTP_SECRET_POSTFIX)
+}
+
+/// Keychain key for the serialized list of unused recovery codes of an account.
+pub fn recovery_codes_key(account_name: &str) -> String {
+    format!("{}{}", account_name, KEY_TOTP_RECOVERY_CODES_POSTFIX)
+}
+
+/// Keychain key for the time step of the last accepted one time password of an account.
+pub fn last_step_key(account_name: &str) -> String {
+    format!("{}{}", account_name, KEY_TOTP_LAST_STEP_POSTFIX)
+}
+
+/// RFC 6238 time based one time password used as a second factor when signing in.
+pub struct SecondFactor {
+    totp: TOTP
+}
+
+impl SecondFactor {
+
+    /// Create a second factor with a new random secret.
+    pub fn generate(account_name: &str) -> blockchain::shared::Result<Self> {
+        let mut secret = vec![0u8; SECRET_LENGTH];
+        OsRng.fill_bytes(&mut secret);
+        Self::new(secret, account_name)
+    }
+
+    /// Restore a second factor from a base32 encoded secret.
+    pub fn from_secret(secret: &str, account_name: &str) -> blockchain::shared::Result<Self> {
+        let secret = Secret::Encoded(secret.to_string()).to_bytes()?;
+        Self::new(secret, account_name)
+    }
+
+    fn new(secret: Vec<u8>, account_name: &str) -> blockchain::shared::Result<Self> {
+        // ':' is used as separator in the otpauth label.
+        let label = account_name.replace(':', "_");
+        let totp = TOTP::new(Algorithm::SHA1, DIGITS, SKEW, STEP, secret, Some(ISSUER.to_string()), label)?;
+        Ok(Self { totp: totp })
+    }
+
+    /// The base32 encoded secret.
+    pub fn secret(&self) -> String {
+        self.totp.get_secret_base32()
+    }
+
+    /// otpauth:// URI used to enroll the secret in an authenticator app.
+    pub fn url(&self) -> String {
+        self.totp.get_url()
+    }
+
+    /// Verify `code` at `now` (ms since the Unix epoch) and return its time step. Codes of `last_step` and earlier
+    /// steps are rejected, so that an accepted code can't be used again (RFC 6238 section 5.2).
+    pub fn verify(&self, code: &str, now: f64, last_step: Option<u64>) -> Option<u64> {
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        let current = (now / 1000.0) as u64 / STEP;
+        (current.saturating_sub(SKEW as u64)..=current + SKEW as u64)
+            .filter(|step| last_step.map_or(true, |last_step| *step > last_step))
+            .find(|step| constant_time_eq(self.totp.generate(step * STEP).as_bytes(), code.as_bytes()))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+/// Generate a set of one time recovery codes.
+pub fn generate_recovery_codes() -> Vec<String> {
+    let mut rng = OsRng;
+    (0..RECOVERY_CODE_COUNT).map(|_| {
+        (0..RECOVERY_CODE_LENGTH).map(|_| {
+            RECOVERY_CODE_ALPHABET[(rng.next_u32() as usize) % RECOVERY_CODE_ALPHABET.len()] as char
+        }).collect()
+    }).collect()
+}
+
+/// Normalize user input of a recovery code (case and separators are ignored).
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_uppercase()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: f64 = 1_700_000_000_000.0;
+
+    fn code(second_factor: &SecondFactor, step: u64) -> String {
+        second_factor.totp.generate(step * STEP)
+    }
+
+    #[test]
+    fn accepts_codes_of_adjacent_steps() {
+        let second_factor = SecondFactor::generate("alice").unwrap();
+        let step = (NOW / 1000.0) as u64 / STEP;
+        assert_eq!(second_factor.verify(&code(&second_factor, step), NOW, None), Some(step));
+        assert_eq!(second_factor.verify(&code(&second_factor, step - 1), NOW, None), Some(step - 1));
+        assert_eq!(second_factor.verify(&code(&second_factor, step + 1), NOW, None), Some(step + 1));
+        assert_eq!(second_factor.verify(&code(&second_factor, step - 2), NOW, None), None);
+    }
+
+    #[test]
+    fn rejects_codes_at_or_before_the_last_accepted_step() {
+        let second_factor = SecondFactor::generate("alice").unwrap();
+        let step = (NOW / 1000.0) as u64 / STEP;
+        assert_eq!(second_factor.verify(&code(&second_factor, step), NOW, Some(step)), None);
+        assert_eq!(second_factor.verify(&code(&second_factor, step - 1), NOW, Some(step)), None);
+        assert_eq!(second_factor.verify(&code(&second_factor, step + 1), NOW, Some(step)), Some(step + 1));
+    }
+}
@@ -10,4 +10,9 @@ macro_rules! log {
 #[wasm_bindgen]
 extern {
     pub fn errorCallback(message: &str);
+}
+
+/// Milliseconds since the Unix epoch, as reported by the JavaScript runtime.
+pub fn now() -> f64 {
+    js_sys::Date::now()
 }
\ No newline at end of file
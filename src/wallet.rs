@@ -1,12 +1,21 @@
 use blockchain::account::*;
-use blockchain::chain_metadata::*;
 use blockchain::chain::*;
+use blockchain::chain_metadata::{is_web_url, ChainInfo, NetworkOverrides};
 use blockchain::layer1::Layer1;
+use blockchain::amount::*;
+use blockchain::explorer::ExplorerApi;
+use blockchain::fees::*;
+use blockchain::history::*;
+use blockchain::nonce::*;
+use blockchain::token::Token;
+use blockchain::transaction::*;
 use serde::{Deserialize, Serialize};
+use crate::metadata::ChainsMetadataCache;
 use crate::storage::*;
 use blockchain::*;
 use crate::log;
 
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
 use std::sync::Weak;
@@ -15,7 +24,11 @@ use std::{sync::Arc, sync::Mutex};
 #[derive(Debug, Clone)]
 pub enum WalletError {
     NotInitialized,
-    ChainNotFound(String)
+    ChainNotFound(String),
+    PreviewNotFound(u32),
+    InvalidFeeSpeed(String),
+    NoPublicRpc(String),
+    InvalidExplorerUrl(String)
 }
 
 
@@ -25,26 +38,67 @@ impl fmt::Display for WalletError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             WalletError::NotInitialized => write!(f, "Wallet not initialized!"),
-            WalletError::ChainNotFound(chain_id) => write!(f, "Chain id {} not found", chain_id) 
+            WalletError::ChainNotFound(chain_id) => write!(f, "Chain id {} not found", chain_id),
+            WalletError::PreviewNotFound(preview_id) => write!(f, "Transfer preview {} not found or expired", preview_id),
+            WalletError::InvalidFeeSpeed(speed) => write!(f, "Unknown fee speed '{}'", speed),
+            WalletError::NoPublicRpc(chain_id) => write!(f, "No public RPC endpoint of chain id {} answered", chain_id),
+            WalletError::InvalidExplorerUrl(url) => write!(f, "Explorer URL {} must start with https:// or http://", url)
         }
     }
 }
 
 const ERC_20_CONTRACT_FILE_NAME: &str = "/ierc20.abi"; 
-const CHAINS_METADATA_URL: &str = "https://chainid.network/chains.json";
 const KEY_SEED_PHRASE_POSTFIX: &str = "_$SEED_PHRASE";
+/** Legacy list of one RPC per chain, migrated to `KEY_CHAINS_POSTFIX`. */
 const KEY_RPCS_POSTFIX: &str = "_$RPCS";
+const KEY_CHAINS_POSTFIX: &str = "_$CHAINS";
 const KEY_TOKENS_POSTFIX: &str = "_$TOKENS";
+const KEY_CONFIRMATION_TARGET_POSTFIX: &str = "_$CONFIRMATION_TARGET";
+const KEY_PENDING_TRANSACTIONS_POSTFIX: &str = "_$PENDING_TRANSACTIONS";
+const KEY_TRANSACTION_HISTORY_POSTFIX: &str = "_$TRANSACTION_HISTORY";
+const KEY_SCAN_CURSOR_POSTFIX: &str = "_$SCAN_CURSOR";
+const KEY_EXPLORER_API_POSTFIX: &str = "_$EXPLORER_API";
+const KEY_NETWORK_OVERRIDES_POSTFIX: &str = "_$NETWORK_OVERRIDES";
+const KEY_VAULT_POSTFIX: &str = "_$VAULT";
+const KEY_VAULT_STATE_POSTFIX: &str = "_$VAULT_STATE";
 const DELIMITER: &str = ";";
+/** Delay (ms) before retrying an offline chain, doubled after every failed attempt up to `MAX_OFFLINE_RETRY_DELAY`. */
+const MIN_OFFLINE_RETRY_DELAY: f64 = 30000.0;
+const MAX_OFFLINE_RETRY_DELAY: f64 = 3600000.0;
 
-impl<T> KeyChain<T> where T: Storage {
+impl<T> KeyChain<T> where T: Storage + ?Sized {
 
     fn get_seed_phase_key(&self, account_id: &str) -> String {
-        format!("{}{}", account_id, KEY_SEED_PHRASE_POSTFIX)
+        seed_phrase_key(account_id)
     }
 }
 
-impl<T> Credentials for KeyChain<T> where T: Storage {
+fn seed_phrase_key(account_id: &str) -> String {
+    format!("{}{}", account_id, KEY_SEED_PHRASE_POSTFIX)
+}
+
+/** Key of the `SealedStorage` holding the duress profile (or filler) of an account. */
+pub(crate) fn vault_key(account_id: &str) -> String {
+    format!("{}{}", account_id, KEY_VAULT_POSTFIX)
+}
+
+/** Keychain key telling the regular profile whether the vault of an account holds a duress profile or a filler. */
+pub(crate) fn vault_state_key(account_id: &str) -> String {
+    format!("{}{}", account_id, KEY_VAULT_STATE_POSTFIX)
+}
+
+/** Permanently delete the password verifier and the seed phrase of an account. */
+pub(crate) fn wipe_credentials(storage: &mut dyn Storage, account_identifier: &AccountIdentifier) {
+    storage.delete(&account_identifier.id);
+    storage.delete(&seed_phrase_key(&account_identifier.id));
+    storage.delete(&crate::totp::secret_key(&account_identifier.id));
+    storage.delete(&crate::totp::recovery_codes_key(&account_identifier.id));
+    storage.delete(&crate::totp::last_step_key(&account_identifier.id));
+    storage.delete(&vault_key(&account_identifier.id));
+    storage.delete(&vault_state_key(&account_identifier.id));
+}
+
+impl<T> Credentials for KeyChain<T> where T: Storage + ?Sized {
 
     fn get_seed_phrase(&self, account_identifier: &AccountIdentifier) -> Option<String> {
         return self.get(&self.get_seed_phase_key(&account_identifier.id)).to_owned()
@@ -67,19 +121,199 @@ pub struct Wallet<C> where C: Credentials {
     account: Arc<Mutex<SigningAccount<C>>>,
     storage: Arc<Mutex<dyn Storage>>,
     portfolio: Arc<Mutex<Portfolio<SigningAccount<C>>>>,
-    chains_metadata: ChainsMetadata,
-    erc_20_contract_source: Option<String>
-    
+    chains_metadata: ChainsMetadataCache,
+    erc_20_contract_source: Option<String>,
+    /** Transfers previewed but not yet confirmed, by preview id. Values are (chain or token id, preview). */
+    previews: HashMap<u32, (String, TransactionPreview)>,
+    next_preview_id: u32,
+    /** Stored chains that couldn't be connected, by chain id. */
+    offline_chains: HashMap<String, OfflineChain>,
+    /** Stored tokens of connected chains that couldn't be loaded, by chain id and lowercase contract address. */
+    offline_tokens: HashMap<(String, String), OfflineToken>
+}
+
+/** Attempts to connect something offline, with a delay doubled after every failed attempt. */
+struct RetrySchedule {
+    attempts: u32,
+    /** Timestamp (ms) of the next attempt. */
+    next_retry: f64
+}
+
+impl RetrySchedule {
+
+    fn new() -> Self {
+        Self { attempts: 0, next_retry: crate::utils::now() + MIN_OFFLINE_RETRY_DELAY }
+    }
+
+    /** True, scheduling the next attempt, if an attempt is due. */
+    fn attempt(&mut self, now: f64) -> bool {
+        if self.next_retry > now {
+            return false;
+        }
+        self.attempts += 1;
+        let delay = MIN_OFFLINE_RETRY_DELAY * 2f64.powi(self.attempts.min(16) as i32);
+        self.next_retry = now + delay.min(MAX_OFFLINE_RETRY_DELAY);
+        true
+    }
+}
+
+/** A stored chain that couldn't be connected. It's shown with its stored configuration and retried in the background. */
+struct OfflineChain {
+    config: ChainConfig,
+    error: String,
+    retry: RetrySchedule
+}
+
+impl OfflineChain {
+
+    fn new(config: ChainConfig, error: String) -> Self {
+        Self { config: config, error: error, retry: RetrySchedule::new() }
+    }
+}
+
+/** A stored token that couldn't be loaded while its chain is connected. It's shown with its error and retried in the background. */
+struct OfflineToken {
+    token: TokenDescriptor,
+    error: String,
+    retry: RetrySchedule
+}
+
+impl OfflineToken {
+
+    fn new(token: TokenDescriptor, error: String) -> Self {
+        Self { token: token, error: error, retry: RetrySchedule::new() }
+    }
+}
+
+/** A chain connected by `Wallet::connect_chain`, with the stored tokens that couldn't be loaded and why. */
+pub(crate) struct ConnectedChain {
+    chain: Layer1,
+    failed_tokens: Vec<(TokenDescriptor, String)>
+}
+
+/** Apply the stored quorum of `chain`, limited to its endpoints if some of them couldn't be connected. */
+fn apply_stored_quorum(chain: &Layer1, quorum: Option<usize>) {
+    if let Err(error) = chain.set_quorum(quorum) {
+        log!("Limiting quorum of chain {} to its endpoints: {}", chain.identifier(), error);
+        // Can't fail, a chain has at least one endpoint.
+        let _ = chain.set_quorum(Some(chain.rpcs().len()));
+    }
+}
+
+fn stored_tokens(storage: &dyn Storage, account_identifier: &AccountIdentifier, chain_id: &str) -> Vec<TokenDescriptor> {
+    if let Some(tokens_serialized) = storage.get(&tokens_key(account_identifier, chain_id)) {
+
+        return serde_json::from_str(&tokens_serialized).unwrap(); 
+    }
+    vec![]
+}
+
+fn offline_token_key(chain_id: &str, contract_address: &str) -> (String, String) {
+    (chain_id.to_string(), contract_address.to_lowercase())
 }
 
 fn rpcs_key(account_identifier: &AccountIdentifier) -> String {
     format!("{}{}", account_identifier.id, KEY_RPCS_POSTFIX)
 }
 
+fn chains_key(account_identifier: &AccountIdentifier) -> String {
+    format!("{}{}", account_identifier.id, KEY_CHAINS_POSTFIX)
+}
+
 fn tokens_key(account_identifier: &AccountIdentifier, chain_id: &str) -> String {
     format!("{}_{}{}", account_identifier.id, chain_id, KEY_TOKENS_POSTFIX)
 }
 
+fn confirmation_target_key(account_identifier: &AccountIdentifier, chain_id: &str) -> String {
+    format!("{}_{}{}", account_identifier.id, chain_id, KEY_CONFIRMATION_TARGET_POSTFIX)
+}
+
+fn explorer_api_key(account_identifier: &AccountIdentifier, chain_id: &str) -> String {
+    format!("{}_{}{}", account_identifier.id, chain_id, KEY_EXPLORER_API_POSTFIX)
+}
+
+fn network_overrides_key(account_identifier: &AccountIdentifier, chain_id: &str) -> String {
+    format!("{}_{}{}", account_identifier.id, chain_id, KEY_NETWORK_OVERRIDES_POSTFIX)
+}
+
+/** Persists per chain data of the `blockchain` crate (pending transactions, history) in the profile storage. */
+struct ChainDataStorage {
+    storage: Arc<Mutex<dyn Storage>>,
+    account_identifier: AccountIdentifier,
+    postfix: &'static str
+}
+
+unsafe impl Send for ChainDataStorage { }
+unsafe impl Sync for ChainDataStorage { }
+
+impl ChainDataStorage {
+
+    fn new(storage: Arc<Mutex<dyn Storage>>, account_identifier: &AccountIdentifier, postfix: &'static str) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self { storage: storage, account_identifier: account_identifier.clone(), postfix: postfix }))
+    }
+
+    fn storage_key(&self, key: &str) -> String {
+        format!("{}_{}{}", self.account_identifier.id, key, self.postfix)
+    }
+
+    fn load(&self, key: &str) -> Option<String> {
+        self.storage.lock().unwrap().get(&self.storage_key(key))
+    }
+
+    fn save(&mut self, key: &str, value: &str) {
+        self.storage.lock().unwrap().set(&self.storage_key(key), value);
+    }
+}
+
+impl PendingTransactionStore for ChainDataStorage {
+    fn load(&self, key: &str) -> Option<String> { ChainDataStorage::load(self, key) }
+    fn save(&mut self, key: &str, value: &str) { ChainDataStorage::save(self, key, value) }
+}
+
+impl HistoryStore for ChainDataStorage {
+    fn load(&self, key: &str) -> Option<String> { ChainDataStorage::load(self, key) }
+    fn save(&mut self, key: &str, value: &str) { ChainDataStorage::save(self, key, value) }
+    fn now(&self) -> u64 { crate::utils::now() as u64 }
+}
+
+/** The chain or token a transfer was sent from. */
+enum TransferSource {
+    Chain(Layer1),
+    Token(Token)
+}
+
+/** A broadcast transfer that can be followed (without holding on to the wallet) until it's final. */
+pub struct PendingTransfer {
+    tracker: TransactionTracker,
+    source: TransferSource
+}
+
+impl PendingTransfer {
+
+    fn new(chain: &Layer1, id: &str, tracker: TransactionTracker) -> Self {
+        let source = match chain.tokens().iter().find(|t| t.identifier() == id) {
+            Some(token) => TransferSource::Token(token.clone()),
+            None => TransferSource::Chain(chain.clone())
+        };
+        Self { tracker: tracker, source: source }
+    }
+
+    pub fn hash(&self) -> String { self.tracker.hash().string_representation() }
+
+    /** Follow the transaction until it's final and refresh the balance it was sent from. */
+    pub async fn track(&self, observer: Option<Weak<Mutex<StatusObserver>>>) -> TransactionStatus {
+        let status = self.tracker.track(observer).await;
+        let result = match &self.source {
+            TransferSource::Chain(chain) => chain.update_balance().await,
+            TransferSource::Token(token) => token.update_balance().await
+        };
+        if let Err(error) = result {
+            log!("Unable to update balance after transfer: {}", error);
+        }
+        status
+    }
+}
+
 unsafe impl<C> Send for Wallet<C> where C: Credentials + Send { }
 
 impl<C> Wallet<C> where C: Credentials + std::fmt::Debug {
@@ -89,10 +323,10 @@ impl<C> Wallet<C> where C: Credentials + std::fmt::Debug {
             account_name: &str, 
             keychain: Arc<Mutex<C>>,
             storage: Arc<Mutex<dyn Storage>>,
+            chains_metadata: ChainsMetadataCache,
             observer: Option<Weak<Mutex<BalanceObserver>>>) -> Self {
         let account_identifier = AccountIdentifier{ id: account_name.to_string() };
 
-        let chains_metadata = ChainsMetadata::new(CHAINS_METADATA_URL.to_string());
         let account = Arc::new(Mutex::new(SigningAccount::new(&account_identifier, keychain.clone())));
         let portfolio = Arc::new(Mutex::new(Portfolio::new(account.clone(), observer)));
             
@@ -102,31 +336,41 @@ impl<C> Wallet<C> where C: Credentials + std::fmt::Debug {
                    portfolio: portfolio,
                    storage: storage,
                    chains_metadata: chains_metadata,
-                   erc_20_contract_source: None }
+                   erc_20_contract_source: None,
+                   previews: HashMap::new(),
+                   next_preview_id: 0,
+                   offline_chains: HashMap::new(),
+                   offline_tokens: HashMap::new() }
     }
 
     pub fn account_identifier(&self) -> AccountIdentifier {
         self.account_identifier.clone()
     }
 
-    pub(crate) async fn initialize(&mut self) -> blockchain::shared::Result<()> {
+    /** Load the portfolio. Chains that can't be connected are kept offline and returned with their errors. */
+    pub(crate) async fn initialize(wallet: &Arc<Mutex<Self>>) -> blockchain::shared::Result<Vec<ChainFailure>> {
         log!("Initializing wallet.");
-        if self.chains_metadata.empty() {
-            self.chains_metadata.download().await?;
-        }
+        let chains_metadata = wallet.lock().unwrap().chains_metadata.clone();
+        chains_metadata.load().await;
         
-        if self.erc_20_contract_source.is_none() {
-            let url: String=  format!("{}{}", &self.local_base_url,ERC_20_CONTRACT_FILE_NAME);
-            self.erc_20_contract_source = Some(reqwest::get(&url).await?.text().await?);
+        let url = {
+            let wallet = wallet.lock().unwrap();
+            wallet.erc_20_contract_source.is_none().then(|| format!("{}{}", &wallet.local_base_url, ERC_20_CONTRACT_FILE_NAME))
+        };
+        if let Some(url) = url {
+            let erc_20_contract_source = reqwest::get(&url).await?.text().await?;
+            wallet.lock().unwrap().erc_20_contract_source = Some(erc_20_contract_source);
         }
         
-        self.account.lock().unwrap().create_wallet();
+        wallet.lock().unwrap().account.lock().unwrap().create_wallet();
         
-        self.load_coins_to_portfolio().await?;
-        self.update_balances().await?;
+        let failures = Self::load_coins_to_portfolio(wallet).await;
+        if let Err(error) = Self::update_balances(wallet).await {
+            log!("Unable to update balances: {}", error);
+        }
 
         log!("Wallet initialized.");
-        Ok(())
+        Ok(failures)
     }
 
     pub fn address(&self) -> String {
@@ -136,33 +380,247 @@ impl<C> Wallet<C> where C: Credentials + std::fmt::Debug {
         "".to_string()
     }
 
-    pub async fn add_chain(&mut self, rpc: String) -> blockchain::shared::Result<String> {
-        let chain_id = self.add_chain_to_portfolio(rpc.clone()).await?;
-        self.store_rpc(rpc.clone());
+    /** Add the chain served by `rpc`. If the chain is already in the portfolio, `rpc` is added as a fallback endpoint.
+     With `chain_id` the endpoint is refused if it serves another chain. Adding a disabled chain enables it again. */
+    pub async fn add_chain(wallet: &Arc<Mutex<Self>>, rpc: String, chain_id: Option<u64>) -> blockchain::shared::Result<String> {
+        // The stored tokens of a disabled or offline chain are restored.
+        let connecting = wallet.lock().unwrap().connect_chain(vec![rpc.clone()], chain_id)?;
+        let connected = connecting.await?;
+        let chain_id = connected.chain.identifier();
+        let existing = wallet.lock().unwrap().portfolio_chain(&chain_id);
+        match existing {
+            Some(existing) => if !existing.rpcs().contains(&rpc) {
+                existing.add_rpc(rpc.clone()).await?;
+            },
+            None => { wallet.lock().unwrap().insert_chain(connected); }
+        }
 
+        let mut wallet = wallet.lock().unwrap();
+        wallet.store_rpc(&chain_id, rpc);
+        wallet.update_chain_config(&chain_id, |config| config.enabled = true);
         Ok(chain_id)
     }
 
-    pub async fn add_token(&mut self, chain_id: String, contract_address: String, symbol: String, decimals: Option<u32>) -> blockchain::shared::Result<()> {
+    /** Disable `chain_id`, removing it from the portfolio but keeping its configuration and tokens, or enable it again. */
+    pub async fn set_chain_enabled(wallet: &Arc<Mutex<Self>>, chain_id: String, enabled: bool) -> blockchain::shared::Result<()> {
+        let config = wallet.lock().unwrap().stored_chain_configs().into_iter().find(|config| config.chain_id == chain_id)
+            .ok_or(WalletError::ChainNotFound(chain_id.clone()))?;
+        if enabled {
+            Self::load_chain(wallet, &config).await?;
+        }
+
+        let mut wallet = wallet.lock().unwrap();
+        if !enabled && wallet.portfolio_chain(&chain_id).is_some() {
+            wallet.detach_chain(&chain_id)?;
+        }
+        wallet.offline_chains.remove(&chain_id);
+        if !enabled {
+            wallet.offline_tokens.retain(|(id, _), _| *id != chain_id);
+        }
+        wallet.update_chain_config(&chain_id, |config| config.enabled = enabled);
+        Ok(())
+    }
+
+    /** Configurations of the chains that are disabled. */
+    pub fn disabled_chains(&self) -> Vec<DisabledChainDescriptor> {
+        self.stored_chain_configs().into_iter()
+            .filter(|config| !config.enabled)
+            .map(|config| {
+                let info = self.chain_info(&config.chain_id);
+                DisabledChainDescriptor { name: info.as_ref().map_or(config.chain_id.clone(), |info| info.name.clone()),
+                                          symbol: info.map(|info| info.symbol).unwrap_or_default(),
+                                          tokens: self.stored_tokens(config.chain_id.clone()).len(),
+                                          id: config.chain_id }
+            })
+            .collect()
+    }
+
+    /** Add a chain known from the metadata, using the first of its public endpoints that serves it.
+     Endpoints requiring an API key are skipped. */
+    pub async fn add_chain_by_id(wallet: &Arc<Mutex<Self>>, chain_id: u64) -> blockchain::shared::Result<String> {
+        let info = wallet.lock().unwrap().chains_metadata.current().get(chain_id).ok_or(WalletError::ChainNotFound(chain_id.to_string()))?;
+        for rpc in info.public_rpcs() {
+            match Self::add_chain(wallet, rpc.clone(), Some(chain_id)).await {
+                Ok(chain_id) => return Ok(chain_id),
+                Err(error) => { log!("Skipping {} of chain {}: {}", rpc, chain_id, error); }
+            }
+        }
+        Err(Box::new(WalletError::NoPublicRpc(chain_id.to_string())))
+    }
+
+    /** Chains of the metadata matching `query` by id, name or native symbol. */
+    pub fn search_chains(&self, query: &str, limit: usize) -> Vec<ChainCandidateDescriptor> {
+        self.chains_metadata.current().search(query, limit).into_iter()
+            .map(|info| ChainCandidateDescriptor { id: info.chain_id.to_string(),
+                                                   name: info.name.clone(),
+                                                   symbol: info.symbol.clone(),
+                                                   testnet: info.testnet,
+                                                   rpcs: info.public_rpcs(),
+                                                   added: self.portfolio_chain(&info.chain_id.to_string()).is_some() })
+            .collect()
+    }
+
+    /** Add a fallback endpoint to `chain_id`. The endpoint has to serve the same chain. */
+    pub async fn add_rpc(wallet: &Arc<Mutex<Self>>, chain_id: String, rpc: String) -> blockchain::shared::Result<()> {
+        let chain = wallet.lock().unwrap().portfolio_chain(&chain_id).ok_or(WalletError::ChainNotFound(chain_id.clone()))?;
+        chain.add_rpc(rpc.clone()).await?;
+        wallet.lock().unwrap().store_rpc(&chain_id, rpc);
+        Ok(())
+    }
+
+    /** Remove an endpoint of `chain_id`. The last endpoint of a chain, or one needed for its quorum, can't be removed. */
+    pub fn remove_rpc(&mut self, chain_id: String, rpc: String) -> blockchain::shared::Result<()> {
+        let chain = self.portfolio_chain(&chain_id).ok_or(WalletError::ChainNotFound(chain_id.clone()))?;
+        chain.remove_rpc(&rpc)?;
+        self.update_chain_config(&chain_id, |config| config.rpcs.retain(|existing| *existing != rpc));
+        Ok(())
+    }
+
+    /** Require `quorum` endpoints of `chain_id` to agree on balances and receipts. `None` trusts the first answer.
+     The quorum can't exceed the number of endpoints. */
+    pub fn set_quorum(&mut self, chain_id: String, quorum: Option<usize>) -> blockchain::shared::Result<()> {
+        let chain = self.portfolio_chain(&chain_id).ok_or(WalletError::ChainNotFound(chain_id.clone()))?;
+        chain.set_quorum(quorum)?;
+        self.update_chain_config(&chain_id, |config| config.quorum = chain.quorum());
+        Ok(())
+    }
+
+    pub async fn add_token(wallet: &Arc<Mutex<Self>>, chain_id: String, contract_address: String, symbol: String, decimals: Option<u32>) -> blockchain::shared::Result<()> {
         
-        let decimals = self.add_token_to_portfolio(chain_id.clone(), contract_address.clone(), symbol.clone(), decimals).await?;
-        let token_descriptor = TokenDescriptor { contract_address: contract_address, symbol: symbol, decimals: decimals };
-        self.store_token(chain_id, token_descriptor)?;
+        let chain = wallet.lock().unwrap().portfolio_chain(&chain_id).ok_or(WalletError::ChainNotFound(chain_id.clone()))?;
+        let token = chain.connect_token(contract_address.clone(), symbol.clone(), decimals).await?;
+        let token_descriptor = TokenDescriptor { contract_address: contract_address, symbol: symbol, decimals: token.decimals(), error: None };
+
+        let mut wallet = wallet.lock().unwrap();
+        if !wallet.insert_token(&chain_id, token) {
+            return Err(Box::new(WalletError::ChainNotFound(chain_id)));
+        }
+        wallet.store_token(chain_id, token_descriptor)?;
+        Ok(())
+    }
+
+    /** Remove `chain_id` and its tokens from the portfolio and from the storage. The transaction history, explorer API and
+     network properties are kept for when the chain is added again. */
+    pub fn remove_chain(&mut self, chain_id: String) -> blockchain::shared::Result<()> {
+        let known = self.stored_chain_configs().iter().any(|config| config.chain_id == chain_id);
+        self.offline_chains.remove(&chain_id);
+        self.offline_tokens.retain(|(id, _), _| *id != chain_id);
+        if self.portfolio_chain(&chain_id).is_some() {
+            self.detach_chain(&chain_id)?;
+        } else if !known {
+            return Err(Box::new(WalletError::ChainNotFound(chain_id)));
+        }
+
+        let mut configs = self.stored_chain_configs();
+        configs.retain(|config| config.chain_id != chain_id);
+        self.store_chain_configs(&configs);
+        self.storage.lock().unwrap().delete(&tokens_key(&self.account_identifier, &chain_id));
+        Ok(())
+    }
+
+    /** Remove `chain_id` from the portfolio and drop the previews of its transfers. */
+    fn detach_chain(&mut self, chain_id: &str) -> blockchain::shared::Result<Layer1> {
+        let chain = self.portfolio.lock().unwrap().remove_chain(chain_id).ok_or(WalletError::ChainNotFound(chain_id.to_string()))?;
+        let token_ids: Vec<String> = chain.tokens().iter().map(|token| token.identifier()).collect();
+        self.previews.retain(|_, (id, _)| *id != chain_id && !token_ids.contains(id));
+        Ok(chain)
+    }
+
+    /** Remove the token of `contract_address` on `chain_id` from the portfolio and from the storage. */
+    pub fn remove_token(&mut self, chain_id: String, contract_address: String) -> blockchain::shared::Result<()> {
+        if self.offline_tokens.remove(&offline_token_key(&chain_id, &contract_address)).is_none() {
+            let portfolio_chains = self.portfolio.lock().unwrap().chains();
+            let token = {
+                let mut chains = portfolio_chains.lock().unwrap();
+                let chain = chains.iter_mut().find(|c| c.identifier() == chain_id).ok_or(WalletError::ChainNotFound(chain_id.clone()))?;
+                chain.remove_token(&contract_address)?
+            };
+            self.previews.retain(|_, (id, _)| *id != token.identifier());
+        }
+
+        let mut tokens = self.stored_tokens(chain_id.clone());
+        tokens.retain(|descriptor| !descriptor.contract_address.eq_ignore_ascii_case(&contract_address));
+        let tokens_string = serde_json::to_string(&tokens)?;
+        self.storage.lock().unwrap().set(&tokens_key(&self.account_identifier, &chain_id), &tokens_string);
         Ok(())
     }
 
-    pub async fn update_balances(&self) -> blockchain::shared::Result<()> {
-        self.portfolio.lock().unwrap().update_balances().await
+    /** Remove all private keys from the wallet. Chains, tokens and balances are kept. */
+    pub fn lock(&mut self) {
+        self.portfolio.lock().unwrap().lock();
+    }
+
+    /** Restore the private keys using `keychain` without re-initializing the portfolio. */
+    pub fn unlock(&mut self, keychain: Arc<Mutex<C>>) -> blockchain::shared::Result<()> {
+        self.account.lock().unwrap().unlock(keychain);
+        self.portfolio.lock().unwrap().unlock()
+    }
+
+    /** Update the balances of all chains and tokens. Like the other operations taking the shared `wallet`, it only locks it
+     while not waiting for the network: the session's background tasks lock it as well, and a recursive lock panics on wasm. */
+    pub async fn update_balances(wallet: &Arc<Mutex<Self>>) -> blockchain::shared::Result<()> {
+        let updating = wallet.lock().unwrap().portfolio.lock().unwrap().update_balances();
+        updating.await
+    }
+
+    pub async fn update_balance(wallet: &Arc<Mutex<Self>>, chain_id: String) -> blockchain::shared::Result<()> {
+        let chain = wallet.lock().unwrap().portfolio_chain(&chain_id);
+        if let Some(chain) = chain {
+            chain.update_balance().await?;
+        }
+
+        Ok(())
     }
 
-    pub async fn update_balance(&self, chain_id: String) -> blockchain::shared::Result<()> {
+    /** Handles to the chains of the portfolio, for background work that must not hold the wallet. */
+    pub fn portfolio_chains(&self) -> Vec<Layer1> {
         let portfolio_chains = self.portfolio.lock().unwrap().chains();
-        for chain in portfolio_chains.lock().unwrap().iter() {
-            if chain.identifier() == chain_id {
-                chain.update_balance().await?;
-            }
+        let chains = portfolio_chains.lock().unwrap().clone();
+        chains
+    }
+
+    pub fn portfolio_chain(&self, chain_id: &str) -> Option<Layer1> {
+        self.portfolio_chains().into_iter().find(|chain| chain.identifier() == chain_id)
+    }
+
+    /** Name, native currency and explorers of a chain of the portfolio, or of any chain known from the metadata or the user's overrides. */
+    pub fn chain_info(&self, chain_id: &str) -> Option<ChainInfo> {
+        if let Some(chain) = self.portfolio_chain(chain_id) {
+            return Some(chain.info().clone());
         }
+        let id = chain_id.parse::<u64>().ok()?;
+        let info = self.chains_metadata.current().get(id);
+        match self.stored_network_overrides(chain_id) {
+            Some(overrides) => Some(info.unwrap_or(ChainInfo::unknown(id)).with_overrides(&overrides)),
+            None => info
+        }
+    }
 
+    /** Network properties of `chain_id` defined by the user. */
+    pub fn network_overrides(&self, chain_id: &str) -> NetworkOverrides {
+        self.stored_network_overrides(chain_id).unwrap_or_default()
+    }
+
+    /** Define or override the network properties of `chain_id`, which doesn't have to be in the portfolio yet.
+     Empty overrides restore the downloaded metadata. The explorer URL has to be an http(s) URL. */
+    pub fn set_network_overrides(&mut self, chain_id: String, overrides: NetworkOverrides) -> blockchain::shared::Result<()> {
+        let id = chain_id.parse::<u64>().map_err(|_| WalletError::ChainNotFound(chain_id.clone()))?;
+        if let Some(explorer_url) = overrides.explorer_url.as_ref().filter(|url| !is_web_url(url)) {
+            return Err(Box::new(WalletError::InvalidExplorerUrl(explorer_url.clone())));
+        }
+        {
+            let mut storage = self.storage.lock().unwrap();
+            if overrides.is_empty() {
+                storage.delete(&network_overrides_key(&self.account_identifier, &chain_id));
+            } else {
+                storage.set(&network_overrides_key(&self.account_identifier, &chain_id), &serde_json::to_string(&overrides)?);
+            }
+        }
+        let info = self.chains_metadata.current().info(id).with_overrides(&overrides);
+        let portfolio_chains = self.portfolio.lock().unwrap().chains();
+        if let Some(chain) = portfolio_chains.lock().unwrap().iter_mut().find(|c| c.identifier() == chain_id) {
+            chain.set_info(info);
+        }
         Ok(())
     }
 
@@ -175,19 +633,115 @@ impl<C> Wallet<C> where C: Credentials + std::fmt::Debug {
                 tokens.push(TokenDescriptor { 
                     contract_address: token.address().string_representation(), 
                     symbol: token.symbol(), 
-                    decimals: token.decimals() });
+                    decimals: token.decimals(),
+                    error: None });
+            }
+            for ((id, _), offline) in self.offline_tokens.iter() {
+                if *id == chain.identifier() {
+                    tokens.push(TokenDescriptor { error: Some(offline.error.clone()), ..offline.token.clone() });
+                }
             }
 
             chains.push(ChainDescriptor { 
                 id: chain.identifier(), 
                 symbol: chain.symbol(), 
-                rpc: chain.rpc().to_string(), 
+                rpc: chain.rpc(), 
+                rpcs: chain.rpcs(),
+                quorum: chain.quorum(),
                 decimals: chain.decimals(),
-                tokens: tokens });
+                explorer_api: chain.explorer_api().map(ExplorerApiDescriptor::new),
+                address_url: chain.info().address_url(&chain.address().string_representation()),
+                info: chain.info().clone(),
+                tokens: tokens,
+                error: None });
+        }
+        for offline in self.offline_chains.values() {
+            let config = &offline.config;
+            let info = self.chain_info(&config.chain_id)
+                .unwrap_or_else(|| ChainInfo::unknown(config.chain_id.parse().unwrap_or_default()));
+            chains.push(ChainDescriptor {
+                id: config.chain_id.clone(),
+                symbol: info.symbol.clone(),
+                rpc: config.rpcs.first().cloned().unwrap_or_default(),
+                rpcs: config.rpcs.clone(),
+                quorum: config.quorum,
+                decimals: info.decimals,
+                explorer_api: self.stored_explorer_api(&config.chain_id).as_ref().map(ExplorerApiDescriptor::new),
+                address_url: info.address_url(&self.address()),
+                info: info,
+                tokens: self.stored_tokens(config.chain_id.clone()),
+                error: Some(offline.error.clone()) });
         }
         chains
     }
 
+    /** Configurations of the offline chains due for another connection attempt. The next attempt is scheduled. */
+    pub(crate) fn offline_chains_to_retry(&mut self) -> Vec<ChainConfig> {
+        let now = crate::utils::now();
+        self.offline_chains.values_mut()
+            .filter_map(|offline| offline.retry.attempt(now).then(|| offline.config.clone()))
+            .collect()
+    }
+
+    /** Chain ids and descriptors of the offline tokens due for another attempt. The next attempt is scheduled. */
+    pub(crate) fn offline_tokens_to_retry(&mut self) -> Vec<(String, TokenDescriptor)> {
+        let now = crate::utils::now();
+        self.offline_tokens.iter_mut()
+            .filter_map(|((chain_id, _), offline)| offline.retry.attempt(now).then(|| (chain_id.clone(), offline.token.clone())))
+            .collect()
+    }
+
+    /** Connect the offline chain `chain_id` and its stored tokens, i.e. once one of its endpoints answers. See `connect_chain`,
+     attach the chain with `attach_chain`. */
+    pub(crate) fn connect_offline_chain(&self, chain_id: &str) -> Option<impl std::future::Future<Output = blockchain::shared::Result<ConnectedChain>>> {
+        let config = &self.offline_chains.get(chain_id)?.config;
+        self.connect_chain(config.rpcs.clone(), config.chain_id.parse().ok()).ok()
+    }
+
+    /** Add a chain connected by `connect_offline_chain` to the portfolio, its failed tokens are retried later. Returns false,
+     dropping the chain, if it was removed, disabled or added again meanwhile. */
+    pub(crate) fn attach_chain(&mut self, connected: ConnectedChain) -> bool {
+        if !self.offline_chains.contains_key(&connected.chain.identifier()) {
+            return false;
+        }
+        self.insert_chain(connected)
+    }
+
+    /** Add a token connected by `Layer1::connect_token` to its chain. Returns false, dropping the token, if it was removed meanwhile. */
+    pub(crate) fn attach_token(&mut self, chain_id: &str, token: Token) -> bool {
+        if self.offline_tokens.remove(&offline_token_key(chain_id, &token.identifier())).is_none() {
+            return false;
+        }
+        self.insert_token(chain_id, token)
+    }
+
+    /** Add a token connected without holding the wallet to its chain. Returns false if the chain isn't in the portfolio. */
+    fn insert_token(&mut self, chain_id: &str, token: Token) -> bool {
+        let portfolio_chains = self.portfolio.lock().unwrap().chains();
+        let mut chains = portfolio_chains.lock().unwrap();
+        match chains.iter_mut().find(|chain| chain.identifier() == chain_id) {
+            Some(chain) => {
+                chain.insert_token(token);
+                true
+            },
+            None => false
+        }
+    }
+
+    /** Record why the offline chain `chain_id` couldn't be connected. */
+    pub(crate) fn set_offline_error(&mut self, chain_id: &str, error: String) {
+        if let Some(offline) = self.offline_chains.get_mut(chain_id) {
+            offline.error = error;
+        }
+    }
+
+    /** Record why the offline token of `contract_address` on `chain_id` couldn't be loaded. */
+    pub(crate) fn set_offline_token_error(&mut self, chain_id: &str, contract_address: &str, error: String) {
+        if let Some(offline) = self.offline_tokens.get_mut(&offline_token_key(chain_id, contract_address)) {
+            offline.error = error;
+        }
+    }
+
     pub fn tokens(&self, chain_id: String) -> Vec<TokenDescriptor> {
         for chain in self.chains() {
             if chain.id == chain_id {
@@ -197,97 +751,324 @@ impl<C> Wallet<C> where C: Credentials + std::fmt::Debug {
         vec![]
     }
 
-    /** Transfer from chain (id) or token (address). */
-    pub async fn transfer(&self, id: String, amount: u64, destination: String) -> blockchain::shared::Result<()> {
-        let chains = self.portfolio.lock().unwrap().chains();
-        let chains = chains.lock().unwrap();
+    /** Transfer from chain (id) or token (address). Returns as soon as the transaction is broadcast. */
+    pub async fn transfer(wallet: &Arc<Mutex<Self>>, id: String, amount: U256, destination: String) -> blockchain::shared::Result<PendingTransfer> {
+        let to = destination.parse().map_err(|_| ChainError::InvalidAddress(destination.clone()))?;
+        let chains = wallet.lock().unwrap().portfolio_chains();
         if let Some(chain) = chains.iter().find(|c| c.identifier() == id) {
-            chain.transfer(destination.parse().unwrap(), amount, None).await?;
-            return Ok(());
+            let hash = chain.transfer(to, amount, None).await?;
+            return Ok(PendingTransfer::new(chain, &id, chain.tracker(hash)));
+        }
+        else if let Some(chain) = chains.iter().find(|c| c.tokens().iter().any(|t| t.identifier() == id)) {
+            let token = chain.tokens().iter().find(|t| t.identifier() == id).unwrap();
+            let hash = token.transfer(to, amount, None).await?;
+            return Ok(PendingTransfer::new(chain, &id, chain.tracker(hash)));
+        }
+
+        Err(Box::new(WalletError::ChainNotFound(id)))
+    }
+
+    /** Resend a pending transaction with higher fees. Returns the replacement. */
+    pub async fn speed_up(wallet: &Arc<Mutex<Self>>, hash: String) -> blockchain::shared::Result<PendingTransfer> {
+        Self::replace(wallet, hash, false).await
+    }
+
+    /** Replace a pending transaction by a 0 value transfer to the own address. Returns the replacement. */
+    pub async fn cancel(wallet: &Arc<Mutex<Self>>, hash: String) -> blockchain::shared::Result<PendingTransfer> {
+        Self::replace(wallet, hash, true).await
+    }
+
+    async fn replace(wallet: &Arc<Mutex<Self>>, hash: String, cancel: bool) -> blockchain::shared::Result<PendingTransfer> {
+        let parsed_hash = hash.parse().map_err(|_| ChainError::UnknownTransaction(hash.clone()))?;
+        let chains = wallet.lock().unwrap().portfolio_chains();
+        let chain = chains.iter()
+            .find(|c| c.nonces().pending_transaction(parsed_hash).is_some())
+            .ok_or(ChainError::UnknownTransaction(hash))?;
+
+        let id = chain.transaction_asset(parsed_hash);
+        let replacement_hash = if cancel { chain.cancel(parsed_hash).await? } else { chain.speed_up(parsed_hash).await? };
+        Ok(PendingTransfer::new(chain, &id, chain.tracker(replacement_hash)))
+    }
+
+    /** Transactions of all chains matching `filter`, newest first. */
+    pub fn transaction_history(&self, filter: &HistoryFilter, offset: usize, limit: usize) -> HistoryPage {
+        let chains = self.chains();
+        let portfolio_chains = self.portfolio.lock().unwrap().chains();
+        let mut records: Vec<TransactionRecord> = portfolio_chains.lock().unwrap().iter()
+            .flat_map(|chain| chain.history().records())
+            .filter(|record| filter.matches(record))
+            .collect();
+        records.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        let total = records.len();
+        let records = records.iter()
+            .skip(offset)
+            .take(limit)
+            .map(|record| TransactionRecordDescriptor::new(record, &chains))
+            .collect();
+        HistoryPage { total: total, offset: offset, records: records }
+    }
+
+    /** Set the number of confirmations after which transfers on `chain_id` are reported as confirmed. */
+    pub fn set_confirmation_target(&mut self, chain_id: String, confirmation_target: u64) -> blockchain::shared::Result<()> {
+        let portfolio_chains = self.portfolio.lock().unwrap().chains();
+        let mut chains = portfolio_chains.lock().unwrap();
+        let chain = chains.iter_mut().find(|c| c.identifier() == chain_id).ok_or(WalletError::ChainNotFound(chain_id.clone()))?;
+        chain.set_confirmation_target(confirmation_target);
+        self.storage.lock().unwrap().set(&confirmation_target_key(&self.account_identifier, &chain_id), &chain.confirmation_target().to_string());
+        Ok(())
+    }
+
+    /** The explorer API configured for `chain_id`, including its key. */
+    pub fn explorer_api(&self, chain_id: &str) -> Option<ExplorerApi> {
+        self.stored_explorer_api(chain_id)
+    }
+
+    /** Set or remove (`None`) the Etherscan compatible explorer API used to import the history of `chain_id`. */
+    pub fn set_explorer_api(&mut self, chain_id: String, api: Option<ExplorerApi>) -> blockchain::shared::Result<()> {
+        let portfolio_chains = self.portfolio.lock().unwrap().chains();
+        let mut chains = portfolio_chains.lock().unwrap();
+        let chain = chains.iter_mut().find(|c| c.identifier() == chain_id).ok_or(WalletError::ChainNotFound(chain_id.clone()))?;
+        let mut storage = self.storage.lock().unwrap();
+        match &api {
+            Some(api) => storage.set(&explorer_api_key(&self.account_identifier, &chain_id), &serde_json::to_string(api)?),
+            None => storage.delete(&explorer_api_key(&self.account_identifier, &chain_id))
+        }
+        chain.set_explorer_api(api);
+        Ok(())
+    }
+
+    /** Estimate the fees of a transfer from chain (id) or token (address). The preview is kept until confirmed. */
+    pub async fn preview_transfer(wallet: &Arc<Mutex<Self>>, id: String, amount: U256, destination: String) -> blockchain::shared::Result<TransferPreviewDescriptor> {
+        let to = destination.parse().map_err(|_| ChainError::InvalidAddress(destination.clone()))?;
+        let chains = wallet.lock().unwrap().portfolio_chains();
+
+        let (preview, chain) = if let Some(chain) = chains.iter().find(|c| c.identifier() == id) {
+            (chain.preview_transfer(to, amount).await?, chain)
         }
-        else if let Some(token) = chains.iter().map(|c| c.tokens()).flat_map(|t| t).find(|t| t.identifier() == id) {
-            token.transfer(destination.parse().unwrap(), amount, None).await?;
-            return Ok(());
+        else if let Some(chain) = chains.iter().find(|c| c.tokens().iter().any(|t| t.identifier() == id)) {
+            let token = chain.tokens().iter().find(|t| t.identifier() == id).unwrap();
+            (token.preview_transfer(to, amount).await?, chain)
         }
-        //else if let Some(token) = chains.iter().map(|c| c.tokens())//.find(|c| c.tokens().iter().f)
+        else {
+            return Err(Box::new(WalletError::ChainNotFound(id)));
+        };
 
+        let mut wallet = wallet.lock().unwrap();
+        let preview_id = wallet.next_preview_id;
+        wallet.next_preview_id += 1;
+        let descriptor = TransferPreviewDescriptor::new(preview_id, &preview, chain);
+        wallet.previews.insert(preview_id, (id, preview));
+        Ok(descriptor)
+    }
+
+    /** Send a previewed transfer with the gas limit and fees shown for `speed` ("slow", "normal" or "fast"). */
+    pub async fn confirm_transfer(wallet: &Arc<Mutex<Self>>, preview_id: u32, speed: &str) -> blockchain::shared::Result<PendingTransfer> {
+        let speed = FeeSpeed::from_name(speed).ok_or(WalletError::InvalidFeeSpeed(speed.to_string()))?;
+        let (id, preview) = wallet.lock().unwrap().previews.remove(&preview_id).ok_or(WalletError::PreviewNotFound(preview_id))?;
+        let chains = wallet.lock().unwrap().portfolio_chains();
+        if let Some(chain) = chains.iter().find(|c| c.identifier() == id) {
+            let hash = chain.send_preview(&preview, speed).await?;
+            return Ok(PendingTransfer::new(chain, &id, chain.tracker(hash)));
+        }
+        else if let Some(chain) = chains.iter().find(|c| c.tokens().iter().any(|t| t.identifier() == id)) {
+            let token = chain.tokens().iter().find(|t| t.identifier() == id).unwrap();
+            let hash = token.send_preview(&preview, speed).await?;
+            return Ok(PendingTransfer::new(chain, &id, chain.tracker(hash)));
+        }
         Err(Box::new(WalletError::ChainNotFound(id)))
     }
 
+    /** Discard a preview that won't be confirmed. */
+    pub fn discard_preview(&mut self, preview_id: u32) {
+        self.previews.remove(&preview_id);
+    }
 }
 
 impl<C> Wallet<C> where C: Credentials + std::fmt::Debug {
 
-    async fn load_coins_to_portfolio(&mut self) -> blockchain::shared::Result<()> {
-        let mut chain_ids = Vec::<String>::new();
-        for rpc in self.stored_rpcs().iter() {
-            log!("Adding |{}| to portfolio", rpc);
-            chain_ids.push(self.add_chain_to_portfolio(rpc.to_owned()).await?);
+    /** Load the enabled chains and their tokens. A chain that can't be connected is kept offline with its stored configuration
+     and doesn't prevent loading the others. Returns the chains that failed to load. */
+    async fn load_coins_to_portfolio(wallet: &Arc<Mutex<Self>>) -> Vec<ChainFailure> {
+        if let Err(error) = Self::migrate_stored_rpcs(wallet).await {
+            log!("Unable to migrate stored RPC endpoints, retrying at next sign in: {}", error);
         }
 
-        for chain_id in chain_ids.iter() {
-            for token_descriptor in self.stored_tokens(chain_id.clone()).iter() {
-                self.add_token_to_portfolio(
-                    chain_id.clone(), 
-                    token_descriptor.contract_address.clone(), 
-                    token_descriptor.symbol.clone(), 
-                    Some(token_descriptor.decimals)).await?;
+        let mut failures = Vec::<ChainFailure>::new();
+        let configs = wallet.lock().unwrap().stored_chain_configs();
+        for config in configs.into_iter().filter(|config| config.enabled) {
+            if let Err(error) = Self::load_chain(wallet, &config).await {
+                log!("Unable to load chain {}: {}", config.chain_id, error);
+                failures.push(ChainFailure { chain_id: config.chain_id.clone(), error: error.to_string() });
+                let mut wallet = wallet.lock().unwrap();
+                if wallet.portfolio_chain(&config.chain_id).is_none() {
+                    wallet.offline_chains.insert(config.chain_id.clone(), OfflineChain::new(config, error.to_string()));
+                }
             }
         }
+        failures
+    }
 
+    /** Add a stored chain and its tokens to the portfolio, unless it's there already (i.e. added by the migration). */
+    async fn load_chain(wallet: &Arc<Mutex<Self>>, config: &ChainConfig) -> blockchain::shared::Result<String> {
+        let loaded = wallet.lock().unwrap().portfolio_chain(&config.chain_id).is_some();
+        if loaded {
+            return Ok(config.chain_id.clone());
+        }
+        log!("Adding |{}| to portfolio", config.rpcs.join(", "));
+        let connecting = wallet.lock().unwrap().connect_chain(config.rpcs.clone(), config.chain_id.parse().ok())?;
+        let connected = connecting.await?;
+        let chain_id = connected.chain.identifier();
+        wallet.lock().unwrap().insert_chain(connected);
+        Ok(chain_id)
+    }
+
+    /** Store the chains of the legacy RPC list by chain id. Entries are only migrated once their chain id is known. */
+    async fn migrate_stored_rpcs(wallet: &Arc<Mutex<Self>>) -> blockchain::shared::Result<()> {
+        let legacy_rpcs: Vec<String> = {
+            let wallet = wallet.lock().unwrap();
+            let storage = wallet.storage.lock().unwrap();
+            let rpcs_string = storage.get(&rpcs_key(&wallet.account_identifier)).unwrap_or("".to_string());
+            rpcs_string.split(DELIMITER).map(String::from).filter(|rpc| rpc.len() > 0).collect()
+        };
+        for (index, rpc) in legacy_rpcs.iter().enumerate() {
+            Self::add_chain(wallet, rpc.clone(), None).await?;
+            let wallet = wallet.lock().unwrap();
+            let mut storage = wallet.storage.lock().unwrap();
+            match legacy_rpcs.len() - index - 1 {
+                0 => storage.delete(&rpcs_key(&wallet.account_identifier)),
+                _ => storage.set(&rpcs_key(&wallet.account_identifier), &legacy_rpcs[index + 1..].join(DELIMITER))
+            }
+        }
         Ok(())
     }
 
-    async fn add_chain_to_portfolio(&mut self, rpc: String) -> blockchain::shared::Result<String> {
+    /** Connect to the chain served by `rpcs` and load its stored tokens, unless the chain is in the portfolio already.
+     Endpoints serving another chain than `chain_id` are refused. The returned future doesn't borrow the wallet, so that it
+     can be awaited without holding it. Add the chain with `insert_chain`. */
+    fn connect_chain(&self, rpcs: Vec<String>, chain_id: Option<u64>) -> blockchain::shared::Result<impl std::future::Future<Output = blockchain::shared::Result<ConnectedChain>>> {
+        let signer = self.account.lock().unwrap().wallet().ok_or(WalletError::NotInitialized)?;
+        let erc_20_contract_source = self.erc_20_contract_source.clone().ok_or(WalletError::NotInitialized)?;
+        let meta_data = self.chains_metadata.current();
+        let loaded: Vec<String> = self.portfolio_chains().iter().map(|chain| chain.identifier()).collect();
+        let storage = self.storage.clone();
+        let account_identifier = self.account_identifier.clone();
+        Ok(async move {
+            let mut chain = Layer1::new(rpcs, signer, &meta_data, erc_20_contract_source, chain_id).await?;
+            let chain_id = chain.identifier();
+            let tokens = if loaded.contains(&chain_id) {
+                vec![]
+            } else {
+                stored_tokens(&*storage.lock().unwrap(), &account_identifier, &chain_id)
+            };
+            let mut failed_tokens = Vec::new();
+            for token_descriptor in tokens {
+                if let Err(error) = chain.add_token(
+                        token_descriptor.contract_address.clone(), 
+                        token_descriptor.symbol.clone(), 
+                        Some(token_descriptor.decimals)).await {
+                    log!("Unable to load token {} of chain {}: {}", token_descriptor.symbol, chain_id, error);
+                    failed_tokens.push((token_descriptor, error.to_string()));
+                }
+            }
+            Ok(ConnectedChain { chain: chain, failed_tokens: failed_tokens })
+        })
+    }
 
-        match self.account.lock().unwrap().wallet() {
-            Some(wallet) => {
-                let chain = Layer1::new(
-                    rpc.clone(), 
-                    wallet, 
-                    &self.chains_metadata, 
-                    self.erc_20_contract_source.clone().unwrap(),
-                    None).await?;
-                let chain_id = chain.identifier();
-                self.portfolio.lock().unwrap().add_chain(chain);
-                Ok(chain_id)
-            },
-            None => Err(Box::new(WalletError::NotInitialized))
+    /** Add a chain connected by `connect_chain` to the portfolio using the stored settings. The tokens that couldn't be loaded
+     are kept offline and retried later. Returns false, dropping the chain, if it's in the portfolio already. */
+    fn insert_chain(&mut self, connected: ConnectedChain) -> bool {
+        let mut chain = connected.chain;
+        let chain_id = chain.identifier();
+        if self.portfolio_chain(&chain_id).is_some() {
+            return false;
+        }
+        self.prepare_chain(&mut chain);
+        if let Some(config) = self.stored_chain_configs().into_iter().find(|config| config.chain_id == chain_id) {
+            apply_stored_quorum(&chain, config.quorum);
+        }
+        self.portfolio.lock().unwrap().add_chain(chain);
+        self.offline_chains.remove(&chain_id);
+        for (token, error) in connected.failed_tokens {
+            self.offline_tokens.insert(offline_token_key(&chain_id, &token.contract_address), OfflineToken::new(token, error));
         }
+        true
     }
 
-    async fn add_token_to_portfolio(&mut self, chain_id: String, address: String, symbol: String, decimals: Option<u32>) -> blockchain::shared::Result<u32> {
-        let portfolio_chains = self.portfolio.lock().unwrap().chains();
-        let mut chains = portfolio_chains.lock().unwrap();
-        for chain in chains.iter_mut() {
-            if chain.identifier() == chain_id {
-                return Ok(chain.add_token(address.clone(), symbol.clone(), decimals).await?);
-            }
+    /** Apply the stored settings of the account to a chain about to be added to the portfolio. */
+    fn prepare_chain(&self, chain: &mut Layer1) {
+        let chain_id = chain.identifier();
+        if let Some(confirmation_target) = self.stored_confirmation_target(&chain_id) {
+            chain.set_confirmation_target(confirmation_target);
         }
-        Err(Box::new(WalletError::ChainNotFound(chain_id)))
+        chain.set_pending_transaction_store(
+            ChainDataStorage::new(self.storage.clone(), &self.account_identifier, KEY_PENDING_TRANSACTIONS_POSTFIX));
+        chain.set_history_store(
+            ChainDataStorage::new(self.storage.clone(), &self.account_identifier, KEY_TRANSACTION_HISTORY_POSTFIX));
+        chain.set_explorer_api(self.stored_explorer_api(&chain_id));
+        if let Some(overrides) = self.stored_network_overrides(&chain_id) {
+            chain.set_info(chain.info().clone().with_overrides(&overrides));
+        }
+        chain.set_scan_cursor_store(
+            ChainDataStorage::new(self.storage.clone(), &self.account_identifier, KEY_SCAN_CURSOR_POSTFIX));
     }
 
+    /** Endpoints of all stored chains. */
     pub fn stored_rpcs(&self) -> Vec<String> {
+        self.stored_chain_configs().into_iter().flat_map(|config| config.rpcs).collect()
+    }
+
+    fn stored_chain_configs(&self) -> Vec<ChainConfig> {
         let storage = self.storage.lock().unwrap();
-        let rpcs_string = storage.get(&rpcs_key(&self.account_identifier)).unwrap_or("".to_string());
-        rpcs_string.split(DELIMITER).map(String::from).filter(|rpc| rpc.len() > 0).collect()
+        storage.get(&chains_key(&self.account_identifier))
+            .and_then(|configs| serde_json::from_str(&configs).ok())
+            .unwrap_or_default()
+    }
+
+    fn store_chain_configs(&mut self, configs: &[ChainConfig]) {
+        let mut storage = self.storage.lock().unwrap();
+        storage.set(&chains_key(&self.account_identifier), &serde_json::to_string(configs).expect("Unable to serialize chains"));
+    }
+
+    /** Apply `change` to the stored configuration of `chain_id`, creating it if needed. */
+    fn update_chain_config<F>(&mut self, chain_id: &str, change: F) where F: FnOnce(&mut ChainConfig) {
+        let mut configs = self.stored_chain_configs();
+        let index = match configs.iter().position(|config| config.chain_id == chain_id) {
+            Some(index) => index,
+            None => {
+                configs.push(ChainConfig { chain_id: chain_id.to_string(), rpcs: vec![], quorum: None, enabled: true });
+                configs.len() - 1
+            }
+        };
+        change(&mut configs[index]);
+        self.store_chain_configs(&configs);
     }
 
     pub fn stored_tokens(&self, chain_id: String) -> Vec<TokenDescriptor> {
+        stored_tokens(&*self.storage.lock().unwrap(), &self.account_identifier, &chain_id)
+    }
+
+    fn stored_confirmation_target(&self, chain_id: &str) -> Option<u64> {
         let storage = self.storage.lock().unwrap();
-        if let Some(tokens_serialized) = storage.get(&tokens_key(&self.account_identifier, &chain_id)) {
+        storage.get(&confirmation_target_key(&self.account_identifier, chain_id)).and_then(|target| target.parse().ok())
+    }
 
-            return serde_json::from_str(&tokens_serialized).unwrap(); 
-        }
-        vec![]
+    fn stored_explorer_api(&self, chain_id: &str) -> Option<ExplorerApi> {
+        let storage = self.storage.lock().unwrap();
+        storage.get(&explorer_api_key(&self.account_identifier, chain_id)).and_then(|api| serde_json::from_str(&api).ok())
     }
 
-    fn store_rpc(&mut self, rpc: String) {
-        let mut rpcs = self.stored_rpcs();
-        let mut storage = self.storage.lock().unwrap();
-        rpcs.push(rpc.clone());
-        let rpcs_string = rpcs.join(DELIMITER);
-        storage.set(&rpcs_key(&self.account_identifier), &rpcs_string);
+    fn stored_network_overrides(&self, chain_id: &str) -> Option<NetworkOverrides> {
+        let storage = self.storage.lock().unwrap();
+        storage.get(&network_overrides_key(&self.account_identifier, chain_id)).and_then(|overrides| serde_json::from_str(&overrides).ok())
+    }
+
+    fn store_rpc(&mut self, chain_id: &str, rpc: String) {
+        self.update_chain_config(chain_id, |config| {
+            if !config.rpcs.contains(&rpc) {
+                config.rpcs.push(rpc);
+            }
+        });
     }
 
     fn store_token(&mut self, chain_id: String, token_descriptor: TokenDescriptor) -> blockchain::shared::Result<()> {
@@ -300,38 +1081,264 @@ impl<C> Wallet<C> where C: Credentials + std::fmt::Debug {
     }
 }
 
+/** Stored configuration of a chain of the portfolio. */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChainConfig {
+    pub chain_id: String,
+    /** Endpoints in order of preference. */
+    pub rpcs: Vec<String>,
+    #[serde(default)]
+    pub quorum: Option<usize>,
+    /** Disabled chains keep their configuration and tokens, but aren't loaded, refreshed or scanned. */
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool
+}
+
+fn enabled_by_default() -> bool { true }
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ChainDescriptor {
     pub id: String,
     pub symbol: String,
+    /** The first reachable endpoint. */
     pub rpc: String,
+    /** All endpoints, in order of preference. */
+    pub rpcs: Vec<String>,
+    pub quorum: Option<usize>,
     pub decimals: u32,
-    pub tokens: Vec<TokenDescriptor>
+    /** Explorer API used to import the history, if configured. */
+    pub explorer_api: Option<ExplorerApiDescriptor>,
+    /** Explorer page of the account, if the chain has an explorer. */
+    pub address_url: Option<String>,
+    /** Name, native currency, public RPCs and explorers of the chain. */
+    pub info: ChainInfo,
+    pub tokens: Vec<TokenDescriptor>,
+    /** Set if the chain is offline: it couldn't be connected and is retried in the background. */
+    pub error: Option<String>
+}
+
+/** An explorer API without its key, which isn't handed out once stored. */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExplorerApiDescriptor {
+    pub base_url: String,
+    /** `true` if an API key is configured. */
+    pub has_key: bool
+}
+
+impl ExplorerApiDescriptor {
+    fn new(api: &ExplorerApi) -> Self {
+        Self { base_url: api.base_url.clone(), has_key: api.api_key.is_some() }
+    }
+}
+
+/** A chain that failed to load, see `Wallet::initialize`. */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChainFailure {
+    pub chain_id: String,
+    pub error: String
+}
+
+/** A chain disabled by the user, see `Wallet::set_chain_enabled`. */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DisabledChainDescriptor {
+    pub id: String,
+    pub name: String,
+    pub symbol: String,
+    /** Number of stored tokens. */
+    pub tokens: usize
+}
+
+/** A chain found by `Wallet::search_chains`. */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChainCandidateDescriptor {
+    pub id: String,
+    pub name: String,
+    pub symbol: String,
+    pub testnet: bool,
+    /** Endpoints usable without an API key, tried in this order by `add_chain_by_id`. */
+    pub rpcs: Vec<String>,
+    /** `true` if the chain is already in the portfolio. */
+    pub added: bool
+}
+
+/** A fee alternative of a transfer preview. Amounts are in the native currency of the chain. */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FeeOptionDescriptor {
+    pub speed: String,
+    /** Maximum fee in the smallest unit. */
+    pub fee: String,
+    /** Maximum native amount debited (value + fee) in the smallest unit. */
+    pub total: String,
+    /** `fee` formatted in whole units, i.e. "0.000021". */
+    pub fee_amount: String,
+    pub total_amount: String
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransferPreviewDescriptor {
+    pub id: u32,
+    /** Symbol of the native currency the fees are paid in. */
+    pub symbol: String,
+    pub gas_limit: String,
+    pub options: Vec<FeeOptionDescriptor>
+}
+
+impl TransferPreviewDescriptor {
+
+    fn new(id: u32, preview: &TransactionPreview, chain: &Layer1) -> Self {
+        let format = AmountFormat::default();
+        let options = preview.options.iter().map(|(speed, fees)| {
+            let fee = preview.fee(fees);
+            let total = preview.total(fees);
+            FeeOptionDescriptor {
+                speed: speed.name().to_string(),
+                fee: fee.to_string(),
+                total: total.to_string(),
+                fee_amount: format.format(fee, chain.decimals()),
+                total_amount: format.format(total, chain.decimals()) }
+        }).collect();
+
+        Self { id: id, symbol: chain.symbol(), gas_limit: preview.gas_limit.to_string(), options: options }
+    }
+}
+
+/** Criteria of a history query. Unset fields match every record. */
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct HistoryFilter {
+    pub chain_id: Option<String>,
+    /** Chain id for native transfers, contract address for token transfers. */
+    pub asset: Option<String>,
+    /** "pending", "included", "confirmed", "failed" or "dropped". */
+    pub status: Option<String>,
+    /** Only records created at or after (ms since the Unix epoch). */
+    pub since: Option<u64>,
+    /** Only records created before (ms since the Unix epoch). */
+    pub until: Option<u64>
+}
+
+impl HistoryFilter {
+
+    fn matches(&self, record: &TransactionRecord) -> bool {
+        self.chain_id.as_ref().map_or(true, |chain_id| *chain_id == record.chain_id) &&
+        self.asset.as_ref().map_or(true, |asset| asset.eq_ignore_ascii_case(&record.asset)) &&
+        self.status.as_ref().map_or(true, |status| status == record.status.name()) &&
+        self.since.map_or(true, |since| record.created_at >= since) &&
+        self.until.map_or(true, |until| record.created_at < until)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransactionRecordDescriptor {
+    pub hash: String,
+    pub chain_id: String,
+    pub asset: String,
+    pub symbol: String,
+    pub from: String,
+    pub to: String,
+    /** In base units of the asset. */
+    pub amount: String,
+    /** `amount` in whole units, i.e. "1.5". */
+    pub amount_formatted: String,
+    /** In base units of the native currency. */
+    pub fee: Option<String>,
+    pub fee_formatted: Option<String>,
+    pub status: String,
+    pub block_number: Option<u64>,
+    /** "outgoing" or "incoming". */
+    pub direction: String,
+    /** Explorer page of the transaction, if the chain has an explorer. */
+    pub explorer_url: Option<String>,
+    pub created_at: u64,
+    pub updated_at: u64
+}
+
+impl TransactionRecordDescriptor {
+
+    pub(crate) fn new(record: &TransactionRecord, chains: &[ChainDescriptor]) -> Self {
+        let chain = chains.iter().find(|chain| chain.id == record.chain_id);
+        let native_decimals = chain.map(|chain| chain.decimals);
+        let asset_decimals = chain.and_then(|chain| {
+            if chain.id == record.asset { return Some(chain.decimals); }
+            chain.tokens.iter().find(|token| token.contract_address.eq_ignore_ascii_case(&record.asset)).map(|token| token.decimals)
+        });
+        let format = AmountFormat::default();
+
+        Self { hash: record.hash.string_representation(),
+               chain_id: record.chain_id.clone(),
+               asset: record.asset.clone(),
+               symbol: record.symbol.clone(),
+               from: record.from.string_representation(),
+               to: record.to.string_representation(),
+               amount: record.amount.to_string(),
+               amount_formatted: asset_decimals.map_or(record.amount.to_string(), |decimals| format.format(record.amount, decimals)),
+               fee: record.fee.map(|fee| fee.to_string()),
+               fee_formatted: record.fee.and_then(|fee| native_decimals.map(|decimals| format.format(fee, decimals))),
+               status: record.status.name().to_string(),
+               block_number: record.block_number,
+               direction: record.direction.name().to_string(),
+               explorer_url: chain.and_then(|chain| chain.info.transaction_url(&record.hash.string_representation())),
+               created_at: record.created_at,
+               updated_at: record.updated_at }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryPage {
+    /** Number of records matching the filter. */
+    pub total: usize,
+    pub offset: usize,
+    pub records: Vec<TransactionRecordDescriptor>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TransactionStatusDescriptor {
+    pub chain_id: String,
+    pub hash: String,
+    /** "pending", "included", "confirmed", "failed" or "dropped". */
+    pub status: String,
+    pub block_number: Option<u64>,
+    pub confirmations: u64,
+    /** Confirmations required before the transaction is reported as confirmed, while "included". */
+    pub confirmation_target: Option<u64>
+}
+
+impl TransactionStatusDescriptor {
+
+    pub fn new(chain_id: &str, hash: &str, status: &TransactionStatus) -> Self {
+        let confirmation_target = match status {
+            TransactionStatus::Included { target, .. } => Some(*target),
+            _ => None
+        };
+        Self { chain_id: chain_id.to_string(), 
+               hash: hash.to_string(), 
+               status: status.name().to_string(), 
+               block_number: status.block_number(), 
+               confirmations: status.confirmations(), 
+               confirmation_target: confirmation_target }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TokenDescriptor {
     pub contract_address: String,
     pub symbol: String,
-    pub decimals: u32
+    pub decimals: u32,
+    /** Why the token couldn't be loaded, never stored. */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>
 }
 
 impl TokenData for TokenDescriptor {
     fn symbol(&self) -> String { self.symbol.clone() }
     fn identifier(&self) -> String { self.contract_address.clone() }
     fn decimals(&self) -> u32 { self.decimals }
-    fn denomiator(&self) -> u128 {
-        let ten: u128 = 10;
-        ten.pow(self.decimals) as u128
-    }
+    fn denomiator(&self) -> U256 { denominator(self.decimals) }
 }
 
 impl TokenData for ChainDescriptor {
     fn symbol(&self) -> String { self.symbol.clone() }
     fn identifier(&self) -> String { self.id.clone() }
     fn decimals(&self) -> u32 { self.decimals }
-    fn denomiator(&self) -> u128 {
-        let ten: u128 = 10;
-        ten.pow(self.decimals) as u128
-    }
+    fn denomiator(&self) -> U256 { denominator(self.decimals) }
 }